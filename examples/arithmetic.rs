@@ -9,7 +9,8 @@ use session_types_ng::*;
 
 // Offers: Add, Negate, Sqrt, Eval
 type SrvQuit = End;
-type SrvAdd  = Recv<mpsc::Value<i64>, Recv<mpsc::Value<i64>, Send<mpsc::Value<i64>, Var<Z>>>>;
+// Both operands of Add travel in a single message
+type SrvAdd  = Recv<(mpsc::Value<i64>, mpsc::Value<i64>), Send<mpsc::Value<i64>, Var<Z>>>;
 type SrvNeg  = Recv<mpsc::Value<i64>, Send<mpsc::Value<i64>, Var<Z>>>;
 type SrvSqrt = Recv<mpsc::Value<f64>, Choose<Send<mpsc::Value<f64>, Var<Z>>, Choose<Var<Z>, Nil>>>;
 type SrvEval = Recv<mpsc::Value<fn(i64) -> bool>, Recv<mpsc::Value<i64>, Send<mpsc::Value<bool>, Var<Z>>>>;
@@ -22,44 +23,35 @@ type Srv =
     Offer<SrvEval, Nil>>>>>;
 
 fn server(chan: Chan<mpsc::Channel, (), Rec<Srv>>) {
-    let mut chan = chan.enter();
-    loop {
-        let maybe_chan = chan
-            .offer()
+    chan.serve(|chan| {
+        chan.offer()
             .option(|chan_close| {
                 chan_close.close();
-                None
+                Loop::Break(())
             })
             .option(|chan_add| {
-                let (chan_add, mpsc::Value(n)) = chan_add.recv().unwrap();
-                let (chan_add, mpsc::Value(m)) = chan_add.recv().unwrap();
-                Some(chan_add.send(mpsc::Value(n + m)).unwrap().zero())
+                let (chan_add, (mpsc::Value(n), mpsc::Value(m))) = chan_add.recv().unwrap();
+                Loop::Continue(chan_add.send_value(n + m).unwrap().zero())
             })
             .option(|chan_neg| {
-                let (chan_neg, mpsc::Value(n)) = chan_neg.recv().unwrap();
-                Some(chan_neg.send(mpsc::Value(-n)).unwrap().zero())
+                let (chan_neg, n) = chan_neg.recv_value().unwrap();
+                Loop::Continue(chan_neg.send_value(-n).unwrap().zero())
             })
             .option(|chan_sqrt| {
-                let (chan_sqrt, mpsc::Value(x)) = chan_sqrt.recv().unwrap();
-                Some(if x >= 0.0 {
-                    chan_sqrt.first().unwrap().send(mpsc::Value(x.sqrt())).unwrap().zero()
+                let (chan_sqrt, x) = chan_sqrt.recv_value().unwrap();
+                Loop::Continue(if x >= 0.0 {
+                    chan_sqrt.first().unwrap().send_value(x.sqrt()).unwrap().zero()
                 } else {
                     chan_sqrt.second().unwrap().zero()
                 })
             })
             .option(|chan_eval| {
-                let (chan_eval, mpsc::Value(f)) = chan_eval.recv().unwrap();
-                let (chan_eval, mpsc::Value(n)) = chan_eval.recv().unwrap();
-                Some(chan_eval.send(mpsc::Value(f(n))).unwrap().zero())
+                let (chan_eval, f) = chan_eval.recv_value().unwrap();
+                let (chan_eval, n) = chan_eval.recv_value().unwrap();
+                Loop::Continue(chan_eval.send_value(f(n)).unwrap().zero())
             })
-            .unwrap();
-
-        if let Some(next_chan) = maybe_chan {
-            chan = next_chan;
-        } else {
-            return;
-        }
-    }
+            .unwrap()
+    })
 }
 
 // `add_client`, `neg_client` and `sqrt_client` are all pretty straightforward
@@ -68,15 +60,13 @@ fn server(chan: Chan<mpsc::Channel, (), Rec<Srv>>) {
 
 type AddCli<R> =
     Choose<End,
-    Choose<Send<mpsc::Value<i64>, Send<mpsc::Value<i64>, Recv<mpsc::Value<i64>, Var<Z>>>>, R>>;
+    Choose<Send<(mpsc::Value<i64>, mpsc::Value<i64>), Recv<mpsc::Value<i64>, Var<Z>>>, R>>;
 
 fn add_client<R>(chan: Chan<mpsc::Channel, (), Rec<AddCli<R>>>) {
     let (chan, mpsc::Value(n)) = chan
         .enter()
         .second().unwrap()
-        .send(mpsc::Value(42)).unwrap()
-        .send(mpsc::Value(1)).unwrap()
-        .recv().unwrap();
+        .call((mpsc::Value(42), mpsc::Value(1))).unwrap();
     println!("add_client: {}", n);
     chan.zero().first().unwrap().close()
 }
@@ -87,11 +77,11 @@ type NegCli<R, S> =
     Choose<Send<mpsc::Value<i64>, Recv<mpsc::Value<i64>, Var<Z>>>, S>>>;
 
 fn neg_client<R, S>(chan: Chan<mpsc::Channel, (), Rec<NegCli<R, S>>>) {
-    let (chan, mpsc::Value(n)) = chan
+    let (chan, n) = chan
         .enter()
         .third().unwrap()
-        .send(mpsc::Value(42)).unwrap()
-        .recv().unwrap();
+        .send_value(42).unwrap()
+        .recv_value().unwrap();
     println!("neg_client: {}", n);
     chan.zero().first().unwrap().close();
 }
@@ -106,10 +96,10 @@ fn sqrt_client<R, S, T>(chan: Chan<mpsc::Channel, (), Rec<SqrtCli<R, S, T>>>) {
     let () = chan
         .enter()
         .fourth().unwrap()
-        .send(mpsc::Value(42.0)).unwrap()
+        .send_value(42.0).unwrap()
         .offer()
         .option(|chan_ok| {
-            let (chan, mpsc::Value(n)) = chan_ok.recv().unwrap();
+            let (chan, n) = chan_ok.recv_value().unwrap();
             println!("sqrt_client: {} OK", n);
             chan.zero().first().unwrap().close();
         })
@@ -134,12 +124,12 @@ fn fn_client<R, S, T>(chan: Chan<mpsc::Channel, (), Rec<PrimeCli<R, S, T>>>) {
         n % 2 == 0
     }
 
-    let (chan, mpsc::Value(b)) = chan
+    let (chan, b) = chan
         .enter()
         .fifth().unwrap()
-        .send(mpsc::Value(even)).unwrap()
-        .send(mpsc::Value(42)).unwrap()
-        .recv().unwrap();
+        .send_value(even).unwrap()
+        .send_value(42).unwrap()
+        .recv_value().unwrap();
     println!("fn_client: {}", b);
     chan.zero().first().unwrap().close();
 }
@@ -163,8 +153,8 @@ fn ask_neg<R, S>(c1: Chan<mpsc::Channel, (), Rec<AskNeg<R, S>>>,
                  c2: Chan<mpsc::Channel, (), DelegChanSend<R, S>>)
     where R: marker::Send + 'static, S: marker::Send + 'static
 {
-    let c1 = c1.enter().third().unwrap().send(mpsc::Value(42)).unwrap();
-    c2.send(mpsc::Value(c1)).unwrap().close();
+    let c1 = c1.enter().third().unwrap().send_value(42).unwrap();
+    c2.send_value(c1).unwrap().close();
 }
 
 type DelegChanRecv<R, S> =
@@ -173,8 +163,8 @@ type DelegChanRecv<R, S> =
 fn get_neg<R, S>(c1: Chan<mpsc::Channel, (), DelegChanRecv<R, S>>)
     where R: marker::Send + 'static, S: marker::Send + 'static
 {
-    let (c1, mpsc::Value(c2)) = c1.recv().unwrap();
-    let (c2, mpsc::Value(n)) = c2.recv().unwrap();
+    let (c1, c2) = c1.recv_value().unwrap();
+    let (c2, n) = c2.recv_value().unwrap();
     println!("get_neg: {}", n);
     c2.zero().first().unwrap().close();
     c1.close();
@@ -1,10 +1,9 @@
 extern crate session_types_ng;
 
-use std::thread::spawn;
-use std::sync::mpsc::{SendError, RecvError};
+use std::sync::mpsc::SendError;
 
 use session_types_ng::*;
-use session_types_ng::mpsc::Value;
+use session_types_ng::mpsc::{Value, RecvError};
 
 type Id = String;
 type Atm = Recv<Value<Id>, Choose<Rec<AtmInner>, Choose<End, Nil>>>;
@@ -15,22 +14,32 @@ type AtmInner =
     Offer<AtmBalance,
     Offer<End, Nil>>>>;
 
+offer_enum! {
+    enum Req for AtmInner {
+        Deposit(AtmDeposit),
+        Withdraw(AtmWithdraw),
+        Balance(AtmBalance),
+        Quit(End),
+    }
+}
+
 type AtmDeposit = Recv<Value<u64>, Send<Value<u64>, Var<Z>>>;
 type AtmWithdraw = Recv<Value<u64>, Choose<Var<Z>, Choose<Var<Z>, Nil>>>;
 type AtmBalance = Send<Value<u64>, Var<Z>>;
 
 type Client = <Atm as HasDual>::Dual;
+type ClientInner = <AtmInner as HasDual>::Dual;
+type ClientInnerChan = Chan<mpsc::Channel, (ClientInner, ()), ClientInner>;
 
 fn approved(id: &Id) -> bool {
     !id.is_empty()
 }
 
-type SendChoiceError = SendError<Box<bool>>;
-type RecvChoiceError = RecvError;
+type SendChoiceError = SendError<Box<mpsc::Choices>>;
 type SendAmountError = SendError<Box<u64>>;
 type RecvOfferError = RecvError;
-type SendIdError = SendError<Box<Id>>;
 
+#[allow(dead_code)]
 #[derive(Debug)]
 enum AtmError {
     RecvId(RecvError),
@@ -57,22 +66,7 @@ fn atm(chan: Chan<mpsc::Channel, (), Atm>) -> Result<(), AtmError> {
 
     let mut balance = 0;
     loop {
-        enum Req<D, W, B, Q> {
-            Deposit(D),
-            Withdraw(W),
-            Balance(B),
-            Quit(Q),
-        }
-
-        let req = chan
-            .offer()
-            .option(Req::Deposit)
-            .option(Req::Withdraw)
-            .option(Req::Balance)
-            .option(Req::Quit)
-            .map_err(AtmError::OfferAtm)?;
-
-        match req {
+        match chan.offer_enum().map_err(AtmError::OfferAtm)? {
             Req::Deposit(chan_deposit) => {
                 let (c, Value(amt)) = chan_deposit.recv().map_err(AtmError::RecvDeposit)?;
                 balance += amt;
@@ -98,82 +92,64 @@ fn atm(chan: Chan<mpsc::Channel, (), Atm>) -> Result<(), AtmError> {
     }
 }
 
-#[derive(Debug)]
-enum ClientError {
-    SendId(SendIdError),
-    LoginFailed(&'static str),
-    OfferClient(RecvOfferError),
-    FailChooseDeposit(SendChoiceError),
-    SendDeposit(SendAmountError),
-    RecvBalance(RecvError),
-    FailChooseQuit(SendChoiceError),
-    FailChooseWithdraw(SendChoiceError),
-    SendWithdraw(SendAmountError),
-}
+// Clients propagate every error with `?` as a single `mpsc::Error`
 
 fn login_client(chan: Chan<mpsc::Channel, (), Client>, login: &str) ->
-    Result<Chan<mpsc::Channel, (<AtmInner as HasDual>::Dual, ()), <AtmInner as HasDual>::Dual>, ClientError>
+    Result<Option<ClientInnerChan>, mpsc::Error>
 {
     let chan = chan
-        .send(Value(login.to_string())).map_err(ClientError::SendId)?
+        .send(Value(login.to_string()))?
         .offer()
-        .option(|chan_success| Ok(chan_success.enter()))
+        .option(|chan_success| Some(chan_success.enter()))
         .option(|chan_fail| {
             chan_fail.close();
-            Err(ClientError::LoginFailed("expected to be approved"))
-        })
-        .map_err(ClientError::OfferClient)??;
+            None
+        })?;
     Ok(chan)
 }
 
-fn deposit_client(chan: Chan<mpsc::Channel, (), Client>) -> Result<(), ClientError> {
-    let chan = login_client(chan, "Deposit Client")?;
+fn deposit_client(chan: Chan<mpsc::Channel, (), Client>) -> Result<(), mpsc::Error> {
+    let chan = login_client(chan, "Deposit Client")?.expect("expected to be approved");
     let (chan, Value(new_balance)) = chan
-        .first().map_err(ClientError::FailChooseDeposit)?
-        .send(Value(200)).map_err(ClientError::SendDeposit)?
-        .recv().map_err(ClientError::RecvBalance)?;
+        .first()?
+        .send(Value(200))?
+        .recv()?;
     println!("deposit_client: new balance: {}", new_balance);
     chan.zero()
-        .fourth().map_err(ClientError::FailChooseQuit)?
+        .fourth()?
         .close();
     Ok(())
 }
 
-fn withdraw_client(chan: Chan<mpsc::Channel, (), Client>) -> Result<(), ClientError> {
-    login_client(chan, "Withdraw Client")?
-        .second().map_err(ClientError::FailChooseWithdraw)?
-        .send(Value(100)).map_err(ClientError::SendWithdraw)?
-        .offer()
-        .option(|chan_success| {
+fn withdraw_client(chan: Chan<mpsc::Channel, (), Client>) -> Result<(), mpsc::Error> {
+    Ok(login_client(chan, "Withdraw Client")?.expect("expected to be approved")
+        .second()?
+        .send(Value(100))?
+        .offer::<Result<_, mpsc::Error>>()
+        .try_option(|chan_success| {
             println!("withdraw_client: successfully withdrew 100");
             chan_success
                 .zero()
-                .fourth().map_err(ClientError::FailChooseQuit)?
+                .fourth()?
                 .close();
             Ok(())
         })
-        .option(|chan_fail| {
+        .try_option(|chan_fail| {
             println!("withdraw_client: could not withdraw. Depositing instead.");
             chan_fail
                 .zero()
-                .first().map_err(ClientError::FailChooseDeposit)?
-                .send(Value(50)).map_err(ClientError::SendDeposit)?
-                .recv().map_err(ClientError::RecvBalance)?
+                .first()?
+                .send(Value(50))?
+                .recv()?
                 .0
                 .zero()
-                .fourth().map_err(ClientError::FailChooseQuit)?
+                .fourth()?
                 .close();
             Ok(())
-        })
-        .map_err(ClientError::OfferClient)?
+        })?)
 }
 
 fn main() {
-    let (atm_chan, client_chan) = mpsc::session_channel();
-    spawn(|| atm(atm_chan).unwrap());
-    deposit_client(client_chan).unwrap();
-
-    let (atm_chan, client_chan) = mpsc::session_channel();
-    spawn(|| atm(atm_chan).unwrap());
-    withdraw_client(client_chan).unwrap();
+    mpsc::connect_try(deposit_client, atm).unwrap();
+    mpsc::connect_try(withdraw_client, atm).unwrap();
 }
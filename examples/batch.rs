@@ -0,0 +1,91 @@
+/// batch.rs
+///
+/// This example compares streaming points one by one using a recursive
+/// protocol with sending all of them at once using a single `SendBatch` step.
+extern crate session_types_ng;
+
+use std::thread::spawn;
+use std::time::{Duration, Instant};
+
+use session_types_ng::*;
+
+#[derive(Debug, Copy, Clone)]
+struct Point(f64, f64, f64);
+
+type StreamSrv = Rec<Choose<End, Choose<Send<mpsc::Value<Point>, Var<Z>>, Nil>>>;
+type StreamCli = <StreamSrv as HasDual>::Dual;
+
+type BatchSrv = SendBatch<mpsc::Value<Point>, End>;
+type BatchCli = <BatchSrv as HasDual>::Dual;
+
+fn stream_srv(chan: Chan<mpsc::Channel, (), StreamSrv>, points: Vec<Point>) {
+    let mut chan = chan.enter();
+    for pt in points {
+        chan = chan.second().unwrap().send_value(pt).unwrap().zero();
+    }
+    chan.first().unwrap().close();
+}
+
+fn stream_cli(chan: Chan<mpsc::Channel, (), StreamCli>) -> Vec<Point> {
+    let mut points = Vec::new();
+    let mut chan = chan.enter();
+    loop {
+        let maybe_chan = chan
+            .offer()
+            .option(|chan_stop| chan_stop.close_with(None))
+            .option(|chan_value| {
+                let (chan, pt) = chan_value.recv_value().unwrap();
+                points.push(pt);
+                Some(chan.zero())
+            })
+            .unwrap();
+
+        if let Some(next_chan) = maybe_chan {
+            chan = next_chan;
+        } else {
+            return points;
+        }
+    }
+}
+
+fn batch_srv(chan: Chan<mpsc::Channel, (), BatchSrv>, points: Vec<Point>) {
+    chan.send_batch(&points).unwrap().close();
+}
+
+fn batch_cli(chan: Chan<mpsc::Channel, (), BatchCli>) -> Vec<Point> {
+    let (chan, points) = chan.recv_batch().unwrap();
+    chan.close();
+    points
+}
+
+fn checksum(points: &[Point]) -> f64 {
+    points.iter().map(|&Point(x, y, z)| x + y + z).sum()
+}
+
+fn bench<P, FS, FC>(points: &[Point], srv: FS, cli: FC) -> Duration where
+    P: HasDual + std::marker::Send + 'static,
+    FS: FnOnce(Chan<mpsc::Channel, (), P>, Vec<Point>) + std::marker::Send + 'static,
+    FC: FnOnce(Chan<mpsc::Channel, (), P::Dual>) -> Vec<Point>,
+{
+    let now = Instant::now();
+    let (tx, rx) = mpsc::session_channel();
+    let sent = points.to_vec();
+    let thread = spawn(move || srv(tx, sent));
+    let received = cli(rx);
+    thread.join().unwrap();
+    let elapsed = now.elapsed();
+    assert_eq!(received.len(), points.len());
+    assert_eq!(checksum(&received), checksum(points));
+    elapsed
+}
+
+fn main() {
+    let points: Vec<_> = (0 .. 100000)
+        .map(|i| Point(i as f64, -i as f64, 0.5 * i as f64))
+        .collect();
+
+    let stream = bench(&points, stream_srv, stream_cli);
+    let batch = bench(&points, batch_srv, batch_cli);
+    println!("bench: {} points streamed one by one in {:?}", points.len(), stream);
+    println!("bench: {} points sent as a single batch in {:?}", points.len(), batch);
+}
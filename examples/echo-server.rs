@@ -7,27 +7,20 @@ use session_types_ng::*;
 type Srv = Offer<End, Offer<Recv<mpsc::Value<String>, Var<Z>>, Nil>>;
 
 fn srv(chan: Chan<mpsc::Channel, (), Rec<Srv>>) {
-    let mut chan = chan.enter();
-    loop {
-        let maybe_chan = chan
-            .offer()
+    chan.serve(|chan| {
+        chan.offer()
             .option(|chan_close| {
                 println!("Closing server.");
                 chan_close.close();
-                None
+                Loop::Break(())
             })
             .option(|chan_recv| {
-                let (chan, mpsc::Value(s)) = chan_recv.recv().unwrap();
+                let (chan, s) = chan_recv.recv_value().unwrap();
                 println!("Received: {}", s);
-                Some(chan.zero())
+                Loop::Continue(chan.zero())
             })
-            .unwrap();
-        if let Some(next_chan) = maybe_chan {
-            chan = next_chan;
-        } else {
-            break;
-        }
-    }
+            .unwrap()
+    })
 }
 
 type Cli = <Srv as HasDual>::Dual;
@@ -47,7 +40,7 @@ fn cli(chan: Chan<mpsc::Channel, (), Rec<Cli>>) {
             "q" => {
                 chan
                     .second().unwrap()
-                    .send(mpsc::Value(format!("{} lines sent", count))).unwrap()
+                    .send_value(format!("{} lines sent", count)).unwrap()
                     .zero()
                     .first().unwrap()
                     .close();
@@ -57,7 +50,7 @@ fn cli(chan: Chan<mpsc::Channel, (), Rec<Cli>>) {
             _ => {
                 chan = chan
                     .second().unwrap()
-                    .send(mpsc::Value(buf.clone())).unwrap()
+                    .send_value(buf.clone()).unwrap()
                     .zero();
                 buf.clear();
                 count += 1;
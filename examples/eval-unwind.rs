@@ -0,0 +1,64 @@
+/// eval-unwind.rs
+///
+/// This example demonstrates a server which evaluates functions supplied by
+/// its clients and survives when one of them panics. Run it with:
+///
+///     cargo run --features catch_unwind --example eval-unwind
+extern crate session_types_ng;
+
+use session_types_ng::*;
+
+type SrvEval = Recv<mpsc::Value<fn(i64) -> bool>, Recv<mpsc::Value<i64>, Send<mpsc::Value<bool>, Var<Z>>>>;
+type Srv = Offer<End, Offer<SrvEval, Nil>>;
+type Cli = <Srv as HasDual>::Dual;
+
+fn server(chan: Chan<mpsc::Channel, (), Rec<Srv>>) {
+    chan.serve(|chan| {
+        chan.offer()
+            .option(|chan_close| {
+                chan_close.close();
+                Loop::Break(())
+            })
+            .option_catch_unwind(
+                |chan_eval| {
+                    let (chan_eval, f) = chan_eval.recv_value().unwrap();
+                    let (chan_eval, n) = chan_eval.recv_value().unwrap();
+                    Loop::Continue(chan_eval.send_value(f(n)).unwrap().zero())
+                },
+                |_panic| {
+                    println!("server: eval function panicked, dropping the session");
+                    Loop::Break(())
+                })
+            .unwrap()
+    })
+}
+
+fn eval_client(f: fn(i64) -> bool, chan: Chan<mpsc::Channel, (), Rec<Cli>>) {
+    let chan = chan
+        .enter()
+        .second().unwrap()
+        .send_value(f).unwrap()
+        .send_value(42).unwrap();
+    match chan.recv_value() {
+        Ok((chan, b)) => {
+            println!("eval_client: {}", b);
+            chan.zero().first().unwrap().close();
+        },
+        Err(e) =>
+            println!("eval_client: session failed: {}", e),
+    }
+}
+
+fn even(n: i64) -> bool {
+    n % 2 == 0
+}
+
+fn broken(n: i64) -> bool {
+    panic!("cannot evaluate {}", n)
+}
+
+fn main() {
+    mpsc::connect(server, |chan| eval_client(even, chan));
+    mpsc::connect(server, |chan| eval_client(broken, chan));
+    mpsc::connect(server, |chan| eval_client(even, chan));
+}
@@ -0,0 +1,103 @@
+/// file-transfer.rs
+///
+/// This example streams a file as a sequence of chunks. When the whole file
+/// has been sent, the sender finishes the session with a checksum, so the
+/// receiver could verify the reassembled contents.
+///
+/// Usage: file-transfer [FILE] (transfers its own source by default)
+extern crate session_types_ng;
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::thread::spawn;
+
+use session_types_ng::*;
+
+const CHUNK_SIZE: usize = 4096;
+
+type Checksum = mpsc::Value<u32>;
+type Chunk = mpsc::Value<Vec<u8>>;
+
+type Sender = Rec<Choose<Send<Checksum, End>, Choose<Send<Chunk, Var<Z>>, Nil>>>;
+type Receiver = <Sender as HasDual>::Dual;
+
+/// Running Adler-32 checksum
+struct Adler32(u32, u32);
+
+impl Adler32 {
+    fn new() -> Adler32 {
+        Adler32(1, 0)
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 = (self.0 + u32::from(byte)) % 65521;
+            self.1 = (self.1 + self.0) % 65521;
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        (self.1 << 16) | self.0
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let mut checksum = Adler32::new();
+    checksum.update(data);
+    checksum.finish()
+}
+
+fn sender(chan: Chan<mpsc::Channel, (), Sender>, mut file: File) {
+    let mut checksum = Adler32::new();
+    let mut chan = chan.enter();
+    loop {
+        let mut chunk = vec![0; CHUNK_SIZE];
+        let bytes_read = file.read(&mut chunk).unwrap();
+        if bytes_read == 0 {
+            break;
+        }
+        chunk.truncate(bytes_read);
+        checksum.update(&chunk);
+        chan = chan.second().unwrap().send_value(chunk).unwrap().zero();
+    }
+    chan.first().unwrap().send_value(checksum.finish()).unwrap().close();
+}
+
+fn receiver(chan: Chan<mpsc::Channel, (), Receiver>) -> Vec<u8> {
+    let mut contents = Vec::new();
+    let mut chunks = 0;
+    let checksum = chan.serve(|chan| {
+        chan.offer()
+            .option(|chan_done| {
+                let (chan, checksum) = chan_done.recv_value().unwrap();
+                chan.close();
+                Loop::Break(checksum)
+            })
+            .option(|chan_chunk| {
+                let (chan, chunk) = chan_chunk.recv_value().unwrap();
+                contents.extend_from_slice(&chunk);
+                chunks += 1;
+                Loop::Continue(chan.zero())
+            })
+            .unwrap()
+    });
+    assert_eq!(checksum, adler32(&contents), "checksum mismatch");
+    println!("receiver: {} bytes in {} chunks, checksum {:08x}", contents.len(), chunks, checksum);
+    contents
+}
+
+fn main() {
+    let path = env::args().nth(1).unwrap_or_else(|| file!().to_string());
+    let mut original = Vec::new();
+    File::open(&path).unwrap().read_to_end(&mut original).unwrap();
+
+    let file = File::open(&path).unwrap();
+    let (tx, rx) = mpsc::session_channel();
+    let thread = spawn(move || sender(tx, file));
+    let received = receiver(rx);
+    thread.join().unwrap();
+
+    assert_eq!(original, received);
+    println!("transferred {} successfully", path);
+}
@@ -7,11 +7,11 @@ extern crate session_types_ng;
 use session_types_ng::*;
 
 fn srv<A>(x: A, c: Chan<mpsc::Channel, (), Send<mpsc::Value<A>, End>>) where A: std::marker::Send + 'static {
-    c.send(mpsc::Value(x)).unwrap().close();
+    c.send_value(x).unwrap().close();
 }
 
 fn cli<A>(c: Chan<mpsc::Channel, (), Recv<mpsc::Value<A>, End>>) where A: std::marker::Send + std::fmt::Debug + 'static {
-    let (c, mpsc::Value(x)) = c.recv().unwrap();
+    let (c, x) = c.recv_value().unwrap();
     println!("{:?}", x);
     c.close();
 }
@@ -0,0 +1,89 @@
+/// An echo server which drops the clients idle for too long.
+/// Every client session gets an idle timeout, and the server parks in
+/// `offer_unless_idle` between requests, so an idle client is closed without
+/// tying up its handler, while a client quitting on its own is told apart.
+extern crate session_types_ng;
+
+use std::thread::{sleep, spawn};
+use std::time::Duration;
+
+use session_types_ng::*;
+
+const IDLE_TIMEOUT: Duration = Duration::from_millis(200);
+
+type Srv = Offer<End, Offer<Recv<mpsc::Value<String>, Send<mpsc::Value<String>, Var<Z>>>, Nil>>;
+type Cli = <Srv as HasDual>::Dual;
+
+fn srv(id: usize, mut chan: Chan<mpsc::Channel, (), Rec<Srv>>) {
+    chan.set_idle_timeout(Some(IDLE_TIMEOUT));
+    let mut chan = chan.enter();
+    loop {
+        let offers = match chan.offer_unless_idle() {
+            Ok(offers) => offers,
+            Err(IdleClosed) => {
+                println!("server {}: client idle for more than {:?}, closed", id, IDLE_TIMEOUT);
+                return;
+            },
+        };
+        let step = offers
+            .option(|chan_quit| {
+                println!("server {}: client quit", id);
+                chan_quit.close_with(None)
+            })
+            .option(|chan_echo| {
+                let (chan, s) = chan_echo.recv_value().unwrap();
+                Some(chan.send_value(s).unwrap().zero())
+            });
+        match step {
+            Ok(Some(next)) =>
+                chan = next,
+            Ok(None) =>
+                return,
+            Err(e) => {
+                println!("server {}: {}", id, e);
+                return;
+            },
+        }
+    }
+}
+
+/// Echo the `lines`, pausing for `pause` before each one.
+fn cli(id: usize, chan: Chan<mpsc::Channel, (), Rec<Cli>>, lines: &[&str], pause: Duration) -> Result<(), mpsc::Error> {
+    let mut chan = chan.enter();
+    for line in lines {
+        sleep(pause);
+        let (next, echo) = chan
+            .second()?
+            .send_value(line.to_string())?
+            .recv_value()?;
+        println!("client {}: {}", id, echo);
+        chan = next.zero();
+    }
+    chan.first()?.close();
+    Ok(())
+}
+
+fn main() {
+    let clients = vec![
+        (vec!["hello", "world"], Duration::from_millis(10)),
+        (vec!["too", "slow"], Duration::from_millis(500)),
+    ];
+    let threads: Vec<_> = clients
+        .into_iter()
+        .enumerate()
+        .map(|(id, (lines, pause))| {
+            let (srv_chan, cli_chan) = mpsc::session_channel();
+            let server = spawn(move || srv(id, srv_chan));
+            let client = spawn(move || {
+                if let Err(e) = cli(id, cli_chan, &lines, pause) {
+                    println!("client {}: gave up: {}", id, e);
+                }
+            });
+            (server, client)
+        })
+        .collect();
+    for (server, client) in threads {
+        server.join().unwrap();
+        client.join().unwrap();
+    }
+}
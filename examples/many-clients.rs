@@ -7,52 +7,33 @@ use rand::random;
 
 use session_types_ng::*;
 
-type Server = Recv<mpsc::Value<u8>, Choose<Send<mpsc::Value<u8>, End>, Choose<End, Nil>>>;
+type Server = Recv<mpsc::Value<u8>, SendOpt<mpsc::Value<u8>, End>>;
 type Client = <Server as HasDual>::Dual;
 
 fn server_handler(chan: Chan<mpsc::Channel, (), Server>) {
-    let (chan, mpsc::Value(n)) = chan.recv().unwrap();
-    match n.checked_add(42) {
-        Some(n) => chan
-            .first().unwrap()
-            .send(mpsc::Value(n)).unwrap()
-            .close(),
-        None => chan
-            .second().unwrap()
-            .close(),
-    }
+    let (chan, n) = chan.recv_value().unwrap();
+    chan.send_opt_value(n.checked_add(42)).unwrap().close();
 }
 
 fn server(rx: Receiver<Chan<mpsc::Channel, (), Server>>) {
     let mut count = 0;
-    loop {
-        match rx.recv() {
-            Ok(c) => {
-                spawn(move || server_handler(c));
-                count += 1;
-            },
-            Err(_) =>
-                break,
-        }
+    while let Ok(c) = rx.recv() {
+        spawn(move || server_handler(c));
+        count += 1;
     }
     println!("Handled {} connections", count);
 }
 
 fn client_handler(chan: Chan<mpsc::Channel, (), Client>) {
     let n = random();
-    chan
-        .send(mpsc::Value(n)).unwrap()
-        .offer()
-        .option(|chan_success| {
-            let (chan, mpsc::Value(n2)) = chan_success.recv().unwrap();
-            chan.close();
-            println!("{} + 42 = {}", n, n2);
-        })
-        .option(|chan_fail| {
-            chan_fail.close();
-            println!("{} + 42 is an overflow :(", n);
-        })
-        .unwrap();
+    let (chan, sum) = chan
+        .send_value(n).unwrap()
+        .recv_opt_value().unwrap();
+    chan.close();
+    match sum {
+        Some(n2) => println!("{} + 42 = {}", n, n2),
+        None => println!("{} + 42 is an overflow :(", n),
+    }
 }
 
 fn main() {
@@ -0,0 +1,63 @@
+/// parallel.rs
+///
+/// This example demonstrates parallel composition of protocols: the client
+/// uploads numbers and a word on two independent sub-sessions driven by
+/// separate threads, and then receives a single summary on the parent session.
+extern crate session_types_ng;
+
+use std::thread::spawn;
+
+use session_types_ng::*;
+
+type Numbers = list::SendList<mpsc::Value<u64>>;
+type Word = Send<mpsc::Value<String>, End>;
+type Summary = Recv<mpsc::Value<String>, End>;
+
+type Cli = Par<Numbers, Word, Summary>;
+type Srv = <Cli as HasDual>::Dual;
+
+fn client(chan: Chan<mpsc::Channel, (), Cli>) {
+    let (chan_numbers, chan_word, join) = chan.par().unwrap();
+
+    let numbers = spawn(move || {
+        let mut chan = chan_numbers.enter();
+        for n in 1 .. 11 {
+            chan = chan.second().unwrap().send_value(n).unwrap().zero();
+        }
+        chan.first().unwrap()
+    });
+    let chan_word = chan_word.send_value("parallel".to_string()).unwrap();
+    let chan_numbers = numbers.join().unwrap();
+
+    let (chan, summary) = join.join(chan_numbers, chan_word).recv_value().unwrap();
+    println!("client: {}", summary);
+    chan.close();
+}
+
+fn server(chan: Chan<mpsc::Channel, (), Srv>) {
+    let (chan_numbers, chan_word, join) = chan.par().unwrap();
+
+    let numbers = spawn(move || {
+        let mut sum = 0;
+        let chan = chan_numbers.serve(|chan| {
+            chan.offer()
+                .option(Loop::Break)
+                .option(|chan_value| {
+                    let (chan, n) = chan_value.recv_value().unwrap();
+                    sum += n;
+                    Loop::Continue(chan.zero())
+                })
+                .unwrap()
+        });
+        (chan, sum)
+    });
+    let (chan_word, word) = chan_word.recv_value().unwrap();
+    let (chan_numbers, sum) = numbers.join().unwrap();
+
+    let summary = format!("sum = {}, word = {}", sum, word);
+    join.join(chan_numbers, chan_word).send_value(summary).unwrap().close();
+}
+
+fn main() {
+    mpsc::connect(client, server);
+}
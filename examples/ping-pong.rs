@@ -0,0 +1,65 @@
+/// ping-pong.rs
+///
+/// This example measures handshake-heavy protocols: the peers bounce a
+/// beat back and forth, once as a unit `Value<()>`, which travels without
+/// an allocation, and once as a `Value<u64>` counter for comparison.
+extern crate session_types_ng;
+
+use std::thread::spawn;
+use std::time::{Duration, Instant};
+
+use session_types_ng::*;
+use session_types_ng::mpsc::Value;
+
+const ROUNDS: usize = 100000;
+
+type PingPong<T> = Rec<Choose<End, Choose<Send<Value<T>, Recv<Value<T>, Var<Z>>>, Nil>>>;
+
+fn pinger<T, F>(chan: Chan<mpsc::Channel, (), PingPong<T>>, mut beat: F) where
+    T: std::marker::Send + 'static,
+    F: FnMut(usize) -> T,
+{
+    let mut chan = chan.enter();
+    for round in 0 .. ROUNDS {
+        let (next_chan, _) = chan.second().unwrap().send_value(beat(round)).unwrap().recv_value().unwrap();
+        chan = next_chan.zero();
+    }
+    chan.first().unwrap().close();
+}
+
+fn ponger<T>(chan: Chan<mpsc::Channel, (), <PingPong<T> as HasDual>::Dual>) -> usize where
+    T: std::marker::Send + 'static,
+{
+    let mut rounds = 0;
+    chan.serve(|chan| {
+        chan.offer()
+            .option(|chan_stop| chan_stop.close_with(Loop::Break(())))
+            .option(|chan_ping| {
+                let (chan, beat) = chan_ping.recv_value().unwrap();
+                rounds += 1;
+                Loop::Continue(chan.send_value(beat).unwrap().zero())
+            })
+            .unwrap()
+    });
+    rounds
+}
+
+fn bench<T, F>(beat: F) -> Duration where
+    T: std::marker::Send + 'static,
+    F: FnMut(usize) -> T + std::marker::Send + 'static,
+{
+    let now = Instant::now();
+    let (chan_pinger, chan_ponger) = mpsc::session_channel();
+    let thread = spawn(move || pinger(chan_pinger, beat));
+    let rounds = ponger::<T>(chan_ponger);
+    thread.join().unwrap();
+    assert_eq!(rounds, ROUNDS);
+    now.elapsed()
+}
+
+fn main() {
+    let unit = bench(|_| ());
+    let counter = bench(|round| round as u64);
+    println!("bench: {} unit round trips in {:?}", ROUNDS, unit);
+    println!("bench: {} u64 round trips in {:?}", ROUNDS, counter);
+}
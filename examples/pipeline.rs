@@ -0,0 +1,102 @@
+// A generic staged pipeline: every stage runs in a thread of its own,
+// receiving a list of values from the previous stage and sending a list to
+// the next one, in the style of the clipper stages of the plane-clip example.
+// `Pipeline` takes care of the wiring, so that a stage is only concerned with
+// its own input and output channels.
+extern crate session_types_ng;
+
+use std::thread::{spawn, JoinHandle};
+
+use session_types_ng::*;
+
+type SendList<A> = list::SendList<mpsc::Value<A>>;
+type RecvList<A> = list::RecvList<mpsc::Value<A>>;
+
+type Input<A> = Chan<mpsc::Channel, (), RecvList<A>>;
+type Output<A> = Chan<mpsc::Channel, (), SendList<A>>;
+
+fn send_list<A, I>(chan: Output<A>, xs: I) where A: std::marker::Send + 'static, I: IntoIterator<Item = A> {
+    let mut chan = chan.enter();
+    for x in xs {
+        chan = chan.second().unwrap().send_value(x).unwrap().zero();
+    }
+    chan.first().unwrap().close();
+}
+
+fn recv_list<A>(chan: Input<A>) -> impl Iterator<Item = A> where A: std::marker::Send + 'static {
+    chan.recv_stream().map(|value| value.unwrap().into_inner())
+}
+
+/// A pipeline whose last stage produces values of type `A`.
+struct Pipeline<A> where A: std::marker::Send + 'static {
+    tail: Input<A>,
+    stages: Vec<JoinHandle<()>>,
+}
+
+impl<A> Pipeline<A> where A: std::marker::Send + 'static {
+    /// Start a pipeline with a source stage sending the values of `source`.
+    fn new<I>(source: I) -> Pipeline<A> where I: IntoIterator<Item = A> + std::marker::Send + 'static {
+        let (tx, rx) = mpsc::session_channel();
+        Pipeline {
+            tail: rx,
+            stages: vec![spawn(move || send_list(tx, source))],
+        }
+    }
+
+    /// Append a stage consuming the output of the pipeline and producing a
+    /// list of values of type `B`.
+    fn stage<B, F>(self, f: F) -> Pipeline<B>
+        where B: std::marker::Send + 'static, F: FnOnce(Input<A>, Output<B>) + std::marker::Send + 'static
+    {
+        let Pipeline { tail, mut stages } = self;
+        let (tx, rx) = mpsc::session_channel();
+        stages.push(spawn(move || f(tail, tx)));
+        Pipeline { tail: rx, stages }
+    }
+
+    /// Append a stage transforming every value independently.
+    fn map<B, F>(self, f: F) -> Pipeline<B>
+        where B: std::marker::Send + 'static, F: FnMut(A) -> B + std::marker::Send + 'static
+    {
+        self.stage(move |input, output| send_list(output, recv_list(input).map(f)))
+    }
+
+    /// Collect the output of the last stage and wait for all the stages.
+    fn sink(self) -> Vec<A> {
+        let values = recv_list(self.tail).collect();
+        for stage in self.stages {
+            stage.join().unwrap();
+        }
+        values
+    }
+}
+
+/// A stateful stage: emits the running sums of its input.
+fn running_sum(input: Input<u64>, output: Output<u64>) {
+    let mut sum = 0;
+    send_list(output, recv_list(input).map(move |x| { sum += x; sum }));
+}
+
+/// A stage emitting a different number of values than it receives: only
+/// the values which differ from the previous one.
+fn dedup<A>(input: Input<A>, output: Output<A>) where A: PartialEq + Clone + std::marker::Send + 'static {
+    let mut last = None;
+    let values = recv_list(input).filter(move |x| {
+        let fresh = last.as_ref() != Some(x);
+        last = Some(x.clone());
+        fresh
+    });
+    send_list(output, values);
+}
+
+fn main() {
+    // Which hundreds do the sums of the first squares pass through?
+    let ranges = Pipeline::new(1 ..= 10u64)
+        .map(|x| x * x)
+        .stage(running_sum)
+        .map(|sum| sum / 100)
+        .stage(dedup)
+        .map(|h| format!("{}..{}", h * 100, h * 100 + 99))
+        .sink();
+    println!("pipeline: {:?}", ranges);
+}
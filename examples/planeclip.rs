@@ -51,42 +51,23 @@ fn intersect(p1: Point, p2: Point, plane: Plane) -> Option<Point> {
     }
 }
 
-type SendList<A> = Rec<Choose<End, Choose<Send<mpsc::Value<A>, Var<Z>>, Nil>>>;
-type RecvList<A> = Rec<Offer<End, Offer<Recv<mpsc::Value<A>, Var<Z>>, Nil>>>;
+type SendList<A> = list::SendList<mpsc::Value<A>>;
+type RecvList<A> = list::RecvList<mpsc::Value<A>>;
 
 fn send_list<A>(chan: Chan<mpsc::Channel, (), SendList<A>>, xs: Vec<A>) where A: std::marker::Send + Copy + 'static
 {
     let mut chan = chan.enter();
     for x in xs {
-        chan = chan.second().unwrap().send(mpsc::Value(x)).unwrap().zero();
+        chan = chan.second().unwrap().send_value(x).unwrap().zero();
     }
     chan.first().unwrap().close();
 }
 
 fn recv_list<A>(chan: Chan<mpsc::Channel, (), RecvList<A>>) -> Vec<A> where A: std::marker::Send + 'static
 {
-    let mut vec = Vec::new();
-    let mut chan = chan.enter();
-    loop {
-        let maybe_chan = chan
-            .offer()
-            .option(|chan_stop| {
-                chan_stop.close();
-                None
-            })
-            .option(|chan_value| {
-                let (chan, mpsc::Value(x)) = chan_value.recv().unwrap();
-                vec.push(x);
-                Some(chan.zero())
-            })
-            .unwrap();
-
-        if let Some(next_chan) = maybe_chan {
-            chan = next_chan;
-        } else {
-            return vec;
-        }
-    }
+    chan.recv_stream()
+        .map(|value| value.unwrap().into_inner())
+        .collect()
 }
 
 fn clipper(plane: Plane,
@@ -99,12 +80,9 @@ fn clipper(plane: Plane,
 
     let maybe_values = ic
         .offer()
-        .option(|chan_stop| {
-            chan_stop.close();
-            None
-        })
+        .option(|chan_stop| chan_stop.close_with(None))
         .option(|chan_value| {
-            let (chan, mpsc::Value(ptz)) = chan_value.recv().unwrap();
+            let (chan, ptz) = chan_value.recv_value().unwrap();
             Some((ptz, chan.zero()))
         })
         .unwrap();
@@ -120,30 +98,27 @@ fn clipper(plane: Plane,
 
     loop {
         if above(pt, plane) {
-            oc = oc.second().unwrap().send(mpsc::Value(pt)).unwrap().zero();
+            oc = oc.second().unwrap().send_value(pt).unwrap().zero();
         }
 
         let maybe_values = ic
             .offer()
-            .option(|chan_stop| {
-                chan_stop.close();
-                None
-            })
+            .option(|chan_stop| chan_stop.close_with(None))
             .option(|chan_value| {
-                let (ic, mpsc::Value(pt2)) = chan_value.recv().unwrap();
+                let (ic, pt2) = chan_value.recv_value().unwrap();
                 Some((pt2, ic.zero()))
             })
             .unwrap();
 
         if let Some((pt2, next_ic)) = maybe_values {
             if let Some(pt) = intersect(pt, pt2, plane) {
-                oc = oc.second().unwrap().send(mpsc::Value(pt)).unwrap().zero();
+                oc = oc.second().unwrap().send_value(pt).unwrap().zero();
             }
             pt = pt2;
             ic = next_ic;
         } else {
             if let Some(pt) = intersect(pt, pt0, plane) {
-                oc = oc.second().unwrap().send(mpsc::Value(pt)).unwrap().zero();
+                oc = oc.second().unwrap().send_value(pt).unwrap().zero();
             }
             oc.first().unwrap().close();
             break;
@@ -0,0 +1,161 @@
+/// request-builder.rs
+///
+/// This example shows how a client library can build a request in stages
+/// across function boundaries: the caller selects a category with `pick`,
+/// hands the narrower channel over to a function which selects a method
+/// of that category, and that one sends the arguments. Every stage is
+/// typed by the part of the protocol left to it, so a stage cannot select
+/// a method of the wrong category.
+extern crate session_types_ng;
+
+use std::collections::HashMap;
+use std::thread::spawn;
+
+use session_types_ng::*;
+use session_types_ng::mpsc::Value;
+
+// Category of account methods: open an account by name, query a balance.
+type Accounts =
+    Offer<Recv<Value<String>, Send<Value<u64>, Var<Z>>>,
+    Offer<Recv<Value<u64>, Send<Value<i64>, Var<Z>>>, Nil>>;
+
+// Category of order methods: place an order for an account, cancel it.
+type Orders =
+    Offer<Recv<Value<(u64, i64)>, Send<Value<u64>, Var<Z>>>,
+    Offer<Recv<Value<u64>, Var<Z>>, Nil>>;
+
+// The rest of the menu after the categories.
+type Tail = Offer<End, Nil>;
+
+type Menu = Offer<Accounts, Offer<Orders, Tail>>;
+type Api = Rec<Menu>;
+
+type MenuCli = <Menu as HasDual>::Dual;
+type AccountsCli = <Accounts as HasDual>::Dual;
+type OrdersCli = <Orders as HasDual>::Dual;
+type TailCli = <Tail as HasDual>::Dual;
+
+type MenuChan = Chan<mpsc::Channel, (MenuCli, ()), MenuCli>;
+type AccountsChan = Chan<mpsc::Channel, (MenuCli, ()), AccountsCli>;
+type OrdersChan = Chan<mpsc::Channel, (MenuCli, ()), OrdersCli>;
+type TailChan = Chan<mpsc::Channel, (MenuCli, ()), TailCli>;
+
+// Stage one: select a category.
+
+fn accounts(chan: MenuChan) -> AccountsChan {
+    chan.pick::<Z>().unwrap()
+}
+
+fn orders(chan: MenuChan) -> OrdersChan {
+    chan.pick::<S<Z>>().unwrap()
+}
+
+fn tail(chan: MenuChan) -> TailChan {
+    chan.skip::<S<S<Z>>>().unwrap()
+}
+
+// Stage two: select a method of the category and send the arguments.
+
+fn open_account(chan: AccountsChan, name: &str) -> (MenuChan, u64) {
+    let (chan, id) = chan.pick::<Z>().unwrap().send_value(name.to_string()).unwrap().recv_value().unwrap();
+    (chan.recurse(), id)
+}
+
+fn balance(chan: AccountsChan, account: u64) -> (MenuChan, i64) {
+    let (chan, balance) = chan.pick::<S<Z>>().unwrap().send_value(account).unwrap().recv_value().unwrap();
+    (chan.recurse(), balance)
+}
+
+fn place_order(chan: OrdersChan, account: u64, amount: i64) -> (MenuChan, u64) {
+    let (chan, order) = chan.pick::<Z>().unwrap().send_value((account, amount)).unwrap().recv_value().unwrap();
+    (chan.recurse(), order)
+}
+
+fn cancel_order(chan: OrdersChan, order: u64) -> MenuChan {
+    chan.pick::<S<Z>>().unwrap().send_value(order).unwrap().recurse()
+}
+
+fn quit(chan: TailChan) {
+    chan.first().unwrap().close();
+}
+
+#[derive(Default)]
+struct Books {
+    accounts: HashMap<u64, (String, i64)>,
+    orders: HashMap<u64, (u64, i64)>,
+    next_id: u64,
+}
+
+impl Books {
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+fn server(chan: Chan<mpsc::Channel, (), Api>) -> Books {
+    let mut books = Books::default();
+    chan.serve(|chan| {
+        chan.offer()
+            .option(|chan_accounts| {
+                Loop::Continue(chan_accounts.offer()
+                    .option(|chan_open| {
+                        let (chan, name) = chan_open.recv_value().unwrap();
+                        let id = books.next_id();
+                        books.accounts.insert(id, (name, 0));
+                        chan.send_value(id).unwrap().recurse()
+                    })
+                    .option(|chan_balance| {
+                        let (chan, id) = chan_balance.recv_value().unwrap();
+                        let balance = books.accounts.get(&id).map_or(0, |&(_, balance)| balance);
+                        chan.send_value(balance).unwrap().recurse()
+                    })
+                    .unwrap())
+            })
+            .option(|chan_orders| {
+                Loop::Continue(chan_orders.offer()
+                    .option(|chan_place| {
+                        let (chan, (account, amount)) = chan_place.recv_value().unwrap();
+                        let order = books.next_id();
+                        books.orders.insert(order, (account, amount));
+                        if let Some(&mut (_, ref mut balance)) = books.accounts.get_mut(&account) {
+                            *balance -= amount;
+                        }
+                        chan.send_value(order).unwrap().recurse()
+                    })
+                    .option(|chan_cancel| {
+                        let (chan, order) = chan_cancel.recv_value().unwrap();
+                        if let Some((account, amount)) = books.orders.remove(&order) {
+                            if let Some(&mut (_, ref mut balance)) = books.accounts.get_mut(&account) {
+                                *balance += amount;
+                            }
+                        }
+                        chan.recurse()
+                    })
+                    .unwrap())
+            })
+            .option(|chan_quit| chan_quit.close_with(Loop::Break(())))
+            .unwrap()
+    });
+    books
+}
+
+fn main() {
+    let (chan_server, chan_client) = mpsc::session_channel();
+    let thread = spawn(move || server(chan_server));
+
+    let chan = chan_client.enter();
+    let (chan, alice) = open_account(accounts(chan), "alice");
+    let (chan, first) = place_order(orders(chan), alice, 30);
+    let (chan, _second) = place_order(orders(chan), alice, 12);
+    let chan = cancel_order(orders(chan), first);
+    let (chan, balance) = balance(accounts(chan), alice);
+    println!("client: balance of account {} is {}", alice, balance);
+    quit(tail(chan));
+
+    let books = thread.join().unwrap();
+    for (id, &(ref name, balance)) in &books.accounts {
+        println!("server: account {} ({}) balance {}, {} open order(s)",
+                 id, name, balance, books.orders.values().filter(|&&(account, _)| account == *id).count());
+    }
+}
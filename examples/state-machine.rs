@@ -0,0 +1,249 @@
+/// state-machine.rs
+///
+/// This example models a vending machine as a session type and shows how
+/// the constructs of the library map onto a state machine:
+///
+/// * a state is a protocol: `Idle` and `Credit` below;
+/// * the transitions a state accepts from the outside are an `Offer`,
+///   one branch per transition;
+/// * a guarded transition, where the machine itself decides where to go
+///   next, is a `Choose` on the machine side;
+/// * a cycle back to a state is a recursion variable: `Var<Z>` refers to
+///   the innermost enclosing `Rec`, `Var<S<Z>>` to the one around it, and
+///   `recurse` follows either of them;
+/// * the final state is `End`.
+///
+/// The customer side is never written by hand: it is the dual of the
+/// machine protocol, so both sides always agree on the transitions.
+extern crate session_types_ng;
+
+use std::collections::HashMap;
+use std::thread::spawn;
+
+use session_types_ng::*;
+use session_types_ng::mpsc::Value;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Item {
+    Cola,
+    Chips,
+    Candy,
+}
+
+impl Item {
+    fn price(self) -> u32 {
+        match self {
+            Item::Cola => 125,
+            Item::Chips => 75,
+            Item::Candy => 60,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Coin {
+    Nickel,
+    Dime,
+    Quarter,
+    Dollar,
+}
+
+impl Coin {
+    fn value(self) -> u32 {
+        match self {
+            Coin::Nickel => 5,
+            Coin::Dime => 10,
+            Coin::Quarter => 25,
+            Coin::Dollar => 100,
+        }
+    }
+}
+
+// State `Idle` (outer recursion): the machine waits for a customer.
+// Transitions: a coin is inserted, which moves the machine to `Credit`,
+// or the machine is shut down, which is the final state.
+type Idle =
+    Offer<Recv<Value<Coin>, Rec<Credit>>,
+    Offer<End, Nil>>;
+
+// State `Credit` (inner recursion): some money has been inserted.
+// Transitions: another coin is inserted (stay in `Credit`), an item is
+// selected (the guarded `Vend` transition) or the purchase is cancelled
+// (refund the credit and return to `Idle`).
+type Credit =
+    Offer<Recv<Value<Coin>, Var<Z>>,
+    Offer<Recv<Value<Item>, Vend>,
+    Offer<Send<Value<u32>, Var<S<Z>>>, Nil>>>;
+
+// Guarded transition: the machine checks the stock and the credit and
+// either dispenses the item along with the change (back to `Idle`),
+// reports the missing amount (stay in `Credit`) or reports that the item
+// is sold out (stay in `Credit`).
+type Vend =
+    Choose<Send<Value<(Item, u32)>, Var<S<Z>>>,
+    Choose<Send<Value<u32>, Var<Z>>,
+    Choose<Var<Z>, Nil>>>;
+
+type Machine = Rec<Idle>;
+
+type Customer = <Machine as HasDual>::Dual;
+type CustomerIdle = <Idle as HasDual>::Dual;
+type CustomerCredit = <Credit as HasDual>::Dual;
+
+// While in `Credit` the environment holds both recursion targets:
+// `Credit` itself for `Var<Z>` and `Idle` for `Var<S<Z>>`.
+type MachineIdleChan = Chan<mpsc::Channel, (Idle, ()), Idle>;
+type MachineCreditChan = Chan<mpsc::Channel, (Credit, (Idle, ())), Credit>;
+type CustomerIdleChan = Chan<mpsc::Channel, (CustomerIdle, ()), CustomerIdle>;
+type CustomerCreditChan = Chan<mpsc::Channel, (CustomerCredit, (CustomerIdle, ())), CustomerCredit>;
+
+#[derive(Default, Debug)]
+struct Stats {
+    sales: u32,
+    revenue: u32,
+    refunds: u32,
+}
+
+fn machine(chan: Chan<mpsc::Channel, (), Machine>, mut stock: HashMap<Item, u32>) -> Stats {
+    let mut stats = Stats::default();
+    chan.serve(|chan_idle| {
+        chan_idle
+            .offer()
+            .option(|chan_coin| {
+                let (chan_credit, coin) = chan_coin.recv_value().unwrap();
+                Loop::Continue(credit(chan_credit, coin.value(), &mut stock, &mut stats))
+            })
+            .option(|chan_shutdown| {
+                chan_shutdown.close();
+                Loop::Break(())
+            })
+            .unwrap()
+    });
+    stats
+}
+
+/// Run the `Credit` state until the machine returns to `Idle`.
+fn credit(chan: Chan<mpsc::Channel, (Idle, ()), Rec<Credit>>,
+          inserted: u32,
+          stock: &mut HashMap<Item, u32>,
+          stats: &mut Stats) -> MachineIdleChan
+{
+    let mut credit = inserted;
+    chan.serve(|chan_credit| {
+        chan_credit
+            .offer()
+            .option(|chan_coin| {
+                let (chan, coin) = chan_coin.recv_value().unwrap();
+                credit += coin.value();
+                Loop::Continue(chan.zero())
+            })
+            .option(|chan_select| {
+                let (chan, item) = chan_select.recv_value().unwrap();
+                vend(chan, item, &mut credit, stock, stats)
+            })
+            .option(|chan_cancel| {
+                let chan = chan_cancel.send_value(credit).unwrap();
+                stats.refunds += credit;
+                Loop::Break(chan.recurse())
+            })
+            .unwrap()
+    })
+}
+
+/// The guarded transition: the machine picks the branch of `Vend`.
+fn vend(chan: Chan<mpsc::Channel, (Credit, (Idle, ())), Vend>,
+        item: Item,
+        credit: &mut u32,
+        stock: &mut HashMap<Item, u32>,
+        stats: &mut Stats) -> Loop<MachineCreditChan, MachineIdleChan>
+{
+    let left = stock.get(&item).cloned().unwrap_or(0);
+    if left == 0 {
+        return Loop::Continue(chan.third().unwrap().zero());
+    }
+    if *credit < item.price() {
+        let missing = item.price() - *credit;
+        return Loop::Continue(chan.second().unwrap().send_value(missing).unwrap().zero());
+    }
+
+    stock.insert(item, left - 1);
+    stats.sales += 1;
+    stats.revenue += item.price();
+    let change = *credit - item.price();
+    *credit = 0;
+    Loop::Break(chan.first().unwrap().send_value((item, change)).unwrap().recurse())
+}
+
+/// Insert the first coin, moving from `Idle` to `Credit`, then the rest.
+fn insert_coins(chan: CustomerIdleChan, coins: &[Coin]) -> CustomerCreditChan {
+    let mut chan = chan.first().unwrap().send_value(coins[0]).unwrap().enter();
+    for &coin in &coins[1 ..] {
+        chan = chan.first().unwrap().send_value(coin).unwrap().zero();
+    }
+    chan
+}
+
+/// Try to buy `item`, topping up with quarters while the machine asks for
+/// more money, and taking the refund if the item is sold out.
+fn buy(chan: CustomerIdleChan, item: Item, coins: &[Coin]) -> CustomerIdleChan {
+    let mut chan = insert_coins(chan, coins);
+    loop {
+        let outcome = chan
+            .second().unwrap()
+            .send_value(item).unwrap()
+            .offer()
+            .option(|chan_dispensed| {
+                let (chan, (item, change)) = chan_dispensed.recv_value().unwrap();
+                println!("customer: got {:?}, change {}", item, change);
+                Loop::Break(chan.recurse())
+            })
+            .option(|chan_short| {
+                let (chan, missing) = chan_short.recv_value().unwrap();
+                println!("customer: {} more needed for {:?}, inserting a quarter", missing, item);
+                Loop::Continue(chan.zero().first().unwrap().send_value(Coin::Quarter).unwrap().zero())
+            })
+            .option(|chan_sold_out| {
+                println!("customer: {:?} is sold out", item);
+                Loop::Break(cancel(chan_sold_out.zero()))
+            })
+            .unwrap();
+
+        match outcome {
+            Loop::Continue(next_chan) =>
+                chan = next_chan,
+            Loop::Break(chan_idle) =>
+                return chan_idle,
+        }
+    }
+}
+
+/// Cancel the purchase, moving from `Credit` back to `Idle`.
+fn cancel(chan: CustomerCreditChan) -> CustomerIdleChan {
+    let (chan, refund) = chan.third().unwrap().recv_value().unwrap();
+    println!("customer: refunded {}", refund);
+    chan.recurse()
+}
+
+fn customer(chan: Chan<mpsc::Channel, (), Customer>) {
+    let chan = chan.enter();
+    // Exact change
+    let chan = buy(chan, Item::Cola, &[Coin::Dollar, Coin::Quarter]);
+    // Not enough money at first: the machine asks for more
+    let chan = buy(chan, Item::Chips, &[Coin::Quarter, Coin::Dime, Coin::Dime]);
+    // The only cola has been sold already
+    let chan = buy(chan, Item::Cola, &[Coin::Dollar, Coin::Dollar]);
+    // Change of mind
+    let chan = cancel(insert_coins(chan, &[Coin::Dime, Coin::Nickel]));
+    // Overpaying
+    let chan = buy(chan, Item::Candy, &[Coin::Dollar]);
+    chan.second().unwrap().close();
+}
+
+fn main() {
+    let stock = [(Item::Cola, 1), (Item::Chips, 3), (Item::Candy, 5)].iter().cloned().collect();
+    let (chan_machine, chan_customer) = mpsc::session_channel();
+    let thread = spawn(move || machine(chan_machine, stock));
+    customer(chan_customer);
+    let stats = thread.join().unwrap();
+    println!("machine: {} sales, revenue {}, refunded {}", stats.sales, stats.revenue, stats.refunds);
+}
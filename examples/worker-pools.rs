@@ -0,0 +1,138 @@
+extern crate session_types_ng;
+
+// Routing the branches of an offer to different worker pools. Cheap
+// branches are served inline by the accepting thread, while every
+// expensive branch has its continuation channel delegated to a pool of
+// its own. Each pool is fed by a queue typed after the protocol of the
+// branch it serves, so the branches may continue with unrelated protocols.
+
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{spawn, JoinHandle};
+
+use session_types_ng::*;
+use session_types_ng::mpsc::Value;
+
+type Factorize = Recv<Value<u64>, Send<Value<Vec<u64>>, End>>;
+type Collatz = Recv<Value<u64>, Send<Value<u32>, End>>;
+type Echo = Recv<Value<String>, Send<Value<String>, End>>;
+
+type Server = Offer<Echo, Offer<Factorize, Offer<Collatz, Nil>>>;
+type Client = <Server as HasDual>::Dual;
+
+type Job<P> = Chan<mpsc::Channel, (), P>;
+
+/// Spawn `size` workers serving the jobs received over the returned queue.
+fn pool<P, F>(size: usize, serve: F) -> (Sender<Job<P>>, Vec<JoinHandle<()>>)
+    where P: std::marker::Send + 'static, F: Fn(Job<P>) + std::marker::Send + Sync + 'static
+{
+    let (tx, rx) = channel();
+    let rx: Arc<Mutex<Receiver<Job<P>>>> = Arc::new(Mutex::new(rx));
+    let serve = Arc::new(serve);
+    let workers = (0 .. size)
+        .map(|_| {
+            let rx = rx.clone();
+            let serve = serve.clone();
+            spawn(move || loop {
+                // Release the lock before serving the job
+                let job = rx.lock().unwrap().recv();
+                match job {
+                    Ok(job) => serve(job),
+                    Err(_) => break,
+                }
+            })
+        })
+        .collect();
+    (tx, workers)
+}
+
+fn factorize(chan: Job<Factorize>) {
+    let (chan, mut n) = chan.recv_value().unwrap();
+    let mut factors = Vec::new();
+    let mut d = 2;
+    while n > 1 {
+        if n % d == 0 {
+            factors.push(d);
+            n /= d;
+        } else {
+            d += 1;
+        }
+    }
+    chan.send_value(factors).unwrap().close();
+}
+
+fn collatz(chan: Job<Collatz>) {
+    let (chan, mut n) = chan.recv_value().unwrap();
+    let mut steps = 0;
+    while n > 1 {
+        n = if n % 2 == 0 { n / 2 } else { 3 * n + 1 };
+        steps += 1;
+    }
+    chan.send_value(steps).unwrap().close();
+}
+
+fn server(connections: Receiver<Job<Server>>) {
+    let (factorize_tx, factorize_workers) = pool(2, factorize);
+    let (collatz_tx, collatz_workers) = pool(4, collatz);
+
+    for chan in connections {
+        chan.offer()
+            .option(|chan_echo| {
+                // Cheap enough to serve right here
+                let (chan, s) = chan_echo.recv_value().unwrap();
+                chan.send_value(s).unwrap().close();
+            })
+            .option(|chan_factorize| factorize_tx.send(chan_factorize).unwrap())
+            .option(|chan_collatz| collatz_tx.send(chan_collatz).unwrap())
+            .unwrap();
+    }
+
+    // Closing the queues stops the workers once they are drained
+    drop(factorize_tx);
+    drop(collatz_tx);
+    for worker in factorize_workers.into_iter().chain(collatz_workers) {
+        worker.join().unwrap();
+    }
+}
+
+fn client(i: u64, chan: Chan<mpsc::Channel, (), Client>) {
+    match i % 3 {
+        0 => {
+            let (chan, s) = chan.first().unwrap().send_value(format!("hello #{}", i)).unwrap().recv_value().unwrap();
+            chan.close();
+            println!("echo: {}", s);
+        },
+        1 => {
+            let n = 600_851_475_143 + i;
+            let (chan, factors) = chan.second().unwrap().send_value(n).unwrap().recv_value().unwrap();
+            chan.close();
+            println!("factors of {}: {:?}", n, factors);
+        },
+        _ => {
+            let n = 27 + i;
+            let (chan, steps) = chan.third().unwrap().send_value(n).unwrap().recv_value().unwrap();
+            chan.close();
+            println!("collatz steps of {}: {}", n, steps);
+        },
+    }
+}
+
+fn main() {
+    let (tx, rx) = channel();
+    let clients: Vec<_> = (0 .. 12)
+        .map(|i| {
+            let tx = tx.clone();
+            spawn(move || {
+                let (srv, cli) = mpsc::session_channel();
+                tx.send(srv).unwrap();
+                client(i, cli);
+            })
+        })
+        .collect();
+    drop(tx);
+
+    server(rx);
+    for client in clients {
+        client.join().unwrap();
+    }
+}
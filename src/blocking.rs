@@ -0,0 +1,115 @@
+//! Running synchronous session handlers from asynchronous code.
+//!
+//! `run_blocking` moves a channel and its handler to a thread of their own
+//! and returns a future of the outcome, so an async server is able to reuse
+//! handlers written against blocking carriers. The future is executor
+//! agnostic: it only relies on the waker it is polled with.
+//!
+//! Both the channel and the handler cross a thread boundary, so they have to
+//! be `Send`: the carrier `SR` and, as channels carry them as marker types,
+//! the environment `E` and the protocol `P` as well. The outcome `R` comes
+//! back across it and has to be `Send` too.
+
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use super::Chan;
+
+struct Shared<R> {
+    outcome: Option<thread::Result<R>>,
+    waker: Option<Waker>,
+}
+
+/// The future of `run_blocking`. Dropping it does not stop the handler, the
+/// outcome is discarded once it is ready.
+pub struct Blocking<R> {
+    shared: Arc<Mutex<Shared<R>>>,
+}
+
+/// Run `handler` over `chan` on a dedicated thread and return a future
+/// resolving to what it returns. A panic of the handler is resumed by the
+/// task polling the future.
+///
+/// ```
+/// use session_types_ng::*;
+/// use session_types_ng::blocking::run_blocking;
+///
+/// let (srv, cli) = mpsc::session_channel::<Recv<mpsc::Value<u32>, End>>();
+/// let sum = run_blocking(srv, |chan| {
+///     let (chan, n) = chan.recv_value().unwrap();
+///     chan.close();
+///     n + 1
+/// });
+/// cli.send_value(41).unwrap().close();
+/// assert_eq!(block_on(sum), 42);
+///
+/// // Any executor will do, here is the simplest one
+/// fn block_on<T>(future: impl std::future::Future<Output = T>) -> T {
+///     use std::sync::Arc;
+///     use std::task::{Context, Poll, Wake};
+///
+///     struct Unpark(std::thread::Thread);
+///
+///     impl Wake for Unpark {
+///         fn wake(self: Arc<Self>) {
+///             self.0.unpark();
+///         }
+///     }
+///
+///     let waker = Arc::new(Unpark(std::thread::current())).into();
+///     let mut cx = Context::from_waker(&waker);
+///     let mut future = Box::pin(future);
+///     loop {
+///         match future.as_mut().poll(&mut cx) {
+///             Poll::Ready(outcome) => return outcome,
+///             Poll::Pending => std::thread::park(),
+///         }
+///     }
+/// }
+/// ```
+pub fn run_blocking<SR, E, P, R, F>(chan: Chan<SR, E, P>, handler: F) -> Blocking<R>
+    where SR: Send + 'static,
+          E: Send + 'static,
+          P: Send + 'static,
+          R: Send + 'static,
+          F: FnOnce(Chan<SR, E, P>) -> R + Send + 'static
+{
+    let shared = Arc::new(Mutex::new(Shared { outcome: None, waker: None }));
+    let worker = shared.clone();
+    thread::spawn(move || {
+        let outcome = panic::catch_unwind(AssertUnwindSafe(move || handler(chan)));
+        let waker = {
+            let mut shared = worker.lock().unwrap_or_else(|e| e.into_inner());
+            shared.outcome = Some(outcome);
+            shared.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    });
+    Blocking { shared }
+}
+
+impl<R> Future for Blocking<R> {
+    type Output = R;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<R> {
+        let mut shared = self.shared.lock().unwrap_or_else(|e| e.into_inner());
+        match shared.outcome.take() {
+            Some(Ok(outcome)) =>
+                Poll::Ready(outcome),
+            Some(Err(payload)) => {
+                drop(shared);
+                panic::resume_unwind(payload)
+            },
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            },
+        }
+    }
+}
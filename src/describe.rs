@@ -0,0 +1,339 @@
+//! Reflection of protocol types into plain data, for example to generate
+//! a matching peer in another language.
+
+use std::any::type_name;
+use std::fmt::Write;
+
+use super::{End, Send, Recv, SendBatch, RecvBatch, SendWithHeader, RecvWithHeader, SendOpt, RecvOpt, Par, Nil, Choose, Offer, TaggedChoose, TaggedOffer, BranchTag, Rec, Var, Z, S};
+
+/// A node of a protocol tree. Payloads are described by their Rust type
+/// names, recursion variables by back-references: `Var { depth: 0 }`
+/// refers to the innermost enclosing `Rec`, `depth: 1` to the one around
+/// it, and so on.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ProtocolNode {
+    End,
+    Send { ty: &'static str, next: Box<ProtocolNode> },
+    Recv { ty: &'static str, next: Box<ProtocolNode> },
+    SendBatch { ty: &'static str, next: Box<ProtocolNode> },
+    RecvBatch { ty: &'static str, next: Box<ProtocolNode> },
+    SendWithHeader { header: &'static str, ty: &'static str, next: Box<ProtocolNode> },
+    RecvWithHeader { header: &'static str, ty: &'static str, next: Box<ProtocolNode> },
+    SendOpt { ty: &'static str, next: Box<ProtocolNode> },
+    RecvOpt { ty: &'static str, next: Box<ProtocolNode> },
+    Par { left: Box<ProtocolNode>, right: Box<ProtocolNode>, next: Box<ProtocolNode> },
+    Choose { branches: Vec<ProtocolNode> },
+    Offer { branches: Vec<ProtocolNode> },
+    TaggedChoose { branches: Vec<(u64, ProtocolNode)> },
+    TaggedOffer { branches: Vec<(u64, ProtocolNode)> },
+    Rec { body: Box<ProtocolNode> },
+    Var { depth: usize },
+}
+
+/// Protocol types which can be reflected into a `ProtocolNode`.
+pub trait Describe {
+    fn describe() -> ProtocolNode;
+}
+
+/// Branch lists of `Choose` and `Offer`.
+pub trait DescribeBranches {
+    fn branches(out: &mut Vec<ProtocolNode>);
+}
+
+/// Branch lists of `TaggedChoose` and `TaggedOffer`.
+pub trait DescribeTaggedBranches {
+    fn branches(out: &mut Vec<(u64, ProtocolNode)>);
+}
+
+/// Peano numbers as values.
+pub trait Depth {
+    const DEPTH: usize;
+}
+
+/// Reflect the protocol `P`.
+pub fn describe<P: Describe>() -> ProtocolNode {
+    P::describe()
+}
+
+impl Depth for Z {
+    const DEPTH: usize = 0;
+}
+
+impl<N: Depth> Depth for S<N> {
+    const DEPTH: usize = N::DEPTH + 1;
+}
+
+impl Describe for End {
+    fn describe() -> ProtocolNode {
+        ProtocolNode::End
+    }
+}
+
+impl<A, P: Describe> Describe for Send<A, P> {
+    fn describe() -> ProtocolNode {
+        ProtocolNode::Send { ty: type_name::<A>(), next: Box::new(P::describe()) }
+    }
+}
+
+impl<A, P: Describe> Describe for Recv<A, P> {
+    fn describe() -> ProtocolNode {
+        ProtocolNode::Recv { ty: type_name::<A>(), next: Box::new(P::describe()) }
+    }
+}
+
+impl<A, P: Describe> Describe for SendBatch<A, P> {
+    fn describe() -> ProtocolNode {
+        ProtocolNode::SendBatch { ty: type_name::<A>(), next: Box::new(P::describe()) }
+    }
+}
+
+impl<A, P: Describe> Describe for RecvBatch<A, P> {
+    fn describe() -> ProtocolNode {
+        ProtocolNode::RecvBatch { ty: type_name::<A>(), next: Box::new(P::describe()) }
+    }
+}
+
+impl<H, A, P: Describe> Describe for SendWithHeader<H, A, P> {
+    fn describe() -> ProtocolNode {
+        ProtocolNode::SendWithHeader { header: type_name::<H>(), ty: type_name::<A>(), next: Box::new(P::describe()) }
+    }
+}
+
+impl<H, A, P: Describe> Describe for RecvWithHeader<H, A, P> {
+    fn describe() -> ProtocolNode {
+        ProtocolNode::RecvWithHeader { header: type_name::<H>(), ty: type_name::<A>(), next: Box::new(P::describe()) }
+    }
+}
+
+impl<A, P: Describe> Describe for SendOpt<A, P> {
+    fn describe() -> ProtocolNode {
+        ProtocolNode::SendOpt { ty: type_name::<A>(), next: Box::new(P::describe()) }
+    }
+}
+
+impl<A, P: Describe> Describe for RecvOpt<A, P> {
+    fn describe() -> ProtocolNode {
+        ProtocolNode::RecvOpt { ty: type_name::<A>(), next: Box::new(P::describe()) }
+    }
+}
+
+impl<P: Describe, Q: Describe, R: Describe> Describe for Par<P, Q, R> {
+    fn describe() -> ProtocolNode {
+        ProtocolNode::Par {
+            left: Box::new(P::describe()),
+            right: Box::new(Q::describe()),
+            next: Box::new(R::describe()),
+        }
+    }
+}
+
+impl DescribeBranches for Nil {
+    fn branches(_out: &mut Vec<ProtocolNode>) {}
+}
+
+impl<P: Describe, L: DescribeBranches> DescribeBranches for Choose<P, L> {
+    fn branches(out: &mut Vec<ProtocolNode>) {
+        out.push(P::describe());
+        L::branches(out);
+    }
+}
+
+impl<P: Describe, L: DescribeBranches> DescribeBranches for Offer<P, L> {
+    fn branches(out: &mut Vec<ProtocolNode>) {
+        out.push(P::describe());
+        L::branches(out);
+    }
+}
+
+impl<P: Describe, L: DescribeBranches> Describe for Choose<P, L> {
+    fn describe() -> ProtocolNode {
+        let mut branches = Vec::new();
+        Self::branches(&mut branches);
+        ProtocolNode::Choose { branches }
+    }
+}
+
+impl<P: Describe, L: DescribeBranches> Describe for Offer<P, L> {
+    fn describe() -> ProtocolNode {
+        let mut branches = Vec::new();
+        Self::branches(&mut branches);
+        ProtocolNode::Offer { branches }
+    }
+}
+
+impl DescribeTaggedBranches for Nil {
+    fn branches(_out: &mut Vec<(u64, ProtocolNode)>) {}
+}
+
+impl<T: BranchTag, P: Describe, L: DescribeTaggedBranches> DescribeTaggedBranches for TaggedChoose<T, P, L> {
+    fn branches(out: &mut Vec<(u64, ProtocolNode)>) {
+        out.push((T::TAG, P::describe()));
+        L::branches(out);
+    }
+}
+
+impl<T: BranchTag, P: Describe, L: DescribeTaggedBranches> DescribeTaggedBranches for TaggedOffer<T, P, L> {
+    fn branches(out: &mut Vec<(u64, ProtocolNode)>) {
+        out.push((T::TAG, P::describe()));
+        L::branches(out);
+    }
+}
+
+impl<T: BranchTag, P: Describe, L: DescribeTaggedBranches> Describe for TaggedChoose<T, P, L> {
+    fn describe() -> ProtocolNode {
+        let mut branches = Vec::new();
+        Self::branches(&mut branches);
+        ProtocolNode::TaggedChoose { branches }
+    }
+}
+
+impl<T: BranchTag, P: Describe, L: DescribeTaggedBranches> Describe for TaggedOffer<T, P, L> {
+    fn describe() -> ProtocolNode {
+        let mut branches = Vec::new();
+        Self::branches(&mut branches);
+        ProtocolNode::TaggedOffer { branches }
+    }
+}
+
+impl<P: Describe> Describe for Rec<P> {
+    fn describe() -> ProtocolNode {
+        ProtocolNode::Rec { body: Box::new(P::describe()) }
+    }
+}
+
+impl<N: Depth> Describe for Var<N> {
+    fn describe() -> ProtocolNode {
+        ProtocolNode::Var { depth: N::DEPTH }
+    }
+}
+
+impl ProtocolNode {
+    /// Render the tree as JSON. Every node is an object with a `"node"`
+    /// field naming its kind and the fields of the corresponding variant;
+    /// tagged branches are objects with `"tag"` and `"protocol"` fields.
+    ///
+    /// ```
+    /// use session_types_ng::*;
+    /// use session_types_ng::describe::describe;
+    ///
+    /// let json = describe::<Rec<Offer<End, Offer<Recv<u8, Var<Z>>, Nil>>>>().to_json();
+    /// assert_eq!(json, concat!(
+    ///     r#"{"node":"Rec","body":{"node":"Offer","branches":["#,
+    ///     r#"{"node":"End"},"#,
+    ///     r#"{"node":"Recv","ty":"u8","next":{"node":"Var","depth":0}}"#,
+    ///     r#"]}}"#));
+    /// ```
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out);
+        out
+    }
+
+    fn write_json(&self, out: &mut String) {
+        match *self {
+            ProtocolNode::End =>
+                out.push_str(r#"{"node":"End"}"#),
+            ProtocolNode::Send { ty, ref next } =>
+                write_step(out, "Send", ty, next),
+            ProtocolNode::Recv { ty, ref next } =>
+                write_step(out, "Recv", ty, next),
+            ProtocolNode::SendBatch { ty, ref next } =>
+                write_step(out, "SendBatch", ty, next),
+            ProtocolNode::RecvBatch { ty, ref next } =>
+                write_step(out, "RecvBatch", ty, next),
+            ProtocolNode::SendWithHeader { header, ty, ref next } =>
+                write_headed_step(out, "SendWithHeader", header, ty, next),
+            ProtocolNode::RecvWithHeader { header, ty, ref next } =>
+                write_headed_step(out, "RecvWithHeader", header, ty, next),
+            ProtocolNode::SendOpt { ty, ref next } =>
+                write_step(out, "SendOpt", ty, next),
+            ProtocolNode::RecvOpt { ty, ref next } =>
+                write_step(out, "RecvOpt", ty, next),
+            ProtocolNode::Par { ref left, ref right, ref next } => {
+                out.push_str(r#"{"node":"Par","left":"#);
+                left.write_json(out);
+                out.push_str(r#","right":"#);
+                right.write_json(out);
+                out.push_str(r#","next":"#);
+                next.write_json(out);
+                out.push('}');
+            },
+            ProtocolNode::Choose { ref branches } =>
+                write_branches(out, "Choose", branches),
+            ProtocolNode::Offer { ref branches } =>
+                write_branches(out, "Offer", branches),
+            ProtocolNode::TaggedChoose { ref branches } =>
+                write_tagged_branches(out, "TaggedChoose", branches),
+            ProtocolNode::TaggedOffer { ref branches } =>
+                write_tagged_branches(out, "TaggedOffer", branches),
+            ProtocolNode::Rec { ref body } => {
+                out.push_str(r#"{"node":"Rec","body":"#);
+                body.write_json(out);
+                out.push('}');
+            },
+            ProtocolNode::Var { depth } => {
+                let _ = write!(out, r#"{{"node":"Var","depth":{}}}"#, depth);
+            },
+        }
+    }
+}
+
+fn write_step(out: &mut String, node: &str, ty: &str, next: &ProtocolNode) {
+    let _ = write!(out, r#"{{"node":"{}","ty":"#, node);
+    write_json_string(out, ty);
+    out.push_str(r#","next":"#);
+    next.write_json(out);
+    out.push('}');
+}
+
+fn write_headed_step(out: &mut String, node: &str, header: &str, ty: &str, next: &ProtocolNode) {
+    let _ = write!(out, r#"{{"node":"{}","header":"#, node);
+    write_json_string(out, header);
+    out.push_str(r#","ty":"#);
+    write_json_string(out, ty);
+    out.push_str(r#","next":"#);
+    next.write_json(out);
+    out.push('}');
+}
+
+fn write_branches(out: &mut String, node: &str, branches: &[ProtocolNode]) {
+    let _ = write!(out, r#"{{"node":"{}","branches":["#, node);
+    for (i, branch) in branches.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        branch.write_json(out);
+    }
+    out.push_str("]}");
+}
+
+fn write_tagged_branches(out: &mut String, node: &str, branches: &[(u64, ProtocolNode)]) {
+    let _ = write!(out, r#"{{"node":"{}","branches":["#, node);
+    for (i, &(tag, ref branch)) in branches.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(out, r#"{{"tag":{},"protocol":"#, tag);
+        branch.write_json(out);
+        out.push('}');
+    }
+    out.push_str("]}");
+}
+
+fn write_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' =>
+                out.push_str("\\\""),
+            '\\' =>
+                out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            },
+            c =>
+                out.push(c),
+        }
+    }
+    out.push('"');
+}
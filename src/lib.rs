@@ -2,9 +2,26 @@
 //!
 //! This is an implementation of *session types* in Rust.
 //! ```
+#![allow(clippy::type_complexity)]
+
+use std::any::type_name;
+#[cfg(feature = "catch_unwind")]
+use std::any::Any;
+#[cfg(feature = "catch_unwind")]
+use std::panic::{catch_unwind, AssertUnwindSafe, UnwindSafe};
 use std::marker::PhantomData;
+use std::fmt;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
 
 pub mod mpsc;
+pub mod list;
+pub mod describe;
+pub mod profile;
+pub mod outcome;
+pub mod blocking;
+#[cfg(feature = "test_support")]
+pub mod test_support;
 
 /// In order to support sending via session channel a value
 /// should implement `ChannelSend` trait.
@@ -15,6 +32,13 @@ pub trait ChannelSend {
     fn send(self, carrier: &mut Self::Crr) -> Result<(), Self::Err>;
 }
 
+/// Values which are handed back by a failed send, so that the caller could
+/// retry (for example on a reconnected channel), should implement
+/// `ChannelSendUnsent` trait.
+pub trait ChannelSendUnsent: ChannelSend + Sized {
+    fn into_unsent(err: Self::Err) -> Self;
+}
+
 /// In order to support receiving via session channel a value
 /// should implement `ChannelRecv` trait.
 pub trait ChannelRecv: Sized {
@@ -24,12 +48,241 @@ pub trait ChannelRecv: Sized {
     fn recv(carrier: &mut Self::Crr) -> Result<Self, Self::Err>;
 }
 
+/// In order to support sending a batch of values in a single message
+/// a value should implement `ChannelSendBatch` trait.
+pub trait ChannelSendBatch {
+    type Crr;
+    type Err;
+    type Item;
+
+    fn send_batch(items: &[Self::Item], carrier: &mut Self::Crr) -> Result<(), Self::Err>;
+}
+
+/// In order to support receiving a batch of values in a single message
+/// a value should implement `ChannelRecvBatch` trait.
+pub trait ChannelRecvBatch {
+    type Crr;
+    type Err;
+    type Item;
+
+    fn recv_batch(carrier: &mut Self::Crr) -> Result<Vec<Self::Item>, Self::Err>;
+}
+
+/// An error of a protocol step which transfers both a choice and a value.
+#[derive(Debug)]
+pub enum ChoiceValueError<C, V> {
+    /// Transferring the choice has failed.
+    Choice(C),
+    /// Transferring the value has failed.
+    Value(V),
+}
+
+/// An error of an offer whose branch handlers may fail, see
+/// `Offers::try_option`.
+#[derive(Debug)]
+pub enum OfferError<H, C> {
+    /// The handler of the chosen branch has failed.
+    Handler(H),
+    /// Receiving the choice has failed.
+    Choice(C),
+}
+
+/// An error of `Chan::recv_validated`.
+#[derive(Debug)]
+pub enum ValidationError<V, R> {
+    /// The received value has been rejected.
+    Invalid(V),
+    /// Receiving the value has failed.
+    Recv(R),
+}
+
+/// The peer has stayed idle for longer than the idle timeout of the carrier,
+/// see `Chan::offer_unless_idle`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct IdleClosed;
+
+/// A peer has selected an offer past the end of the offered protocols list.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct InvalidChoice;
+
+/// An underlying transport for session channels.
+///
+/// Carriers are free to buffer outgoing data: a value or a choice passed to
+/// the carrier is not guaranteed to reach the peer until the carrier is
+/// flushed (see `Flush` and `AutoFlush`) or dropped.
+///
+/// A misbehaving peer may select an offer which does not exist, so
+/// `RecvChoiceErr` should be able to report it as `InvalidChoice`.
 pub trait Carrier: Sized {
     type SendChoiceErr;
     fn send_choice(&mut self, choice: bool) -> Result<(), Self::SendChoiceErr>;
 
-    type RecvChoiceErr;
+    type RecvChoiceErr: From<InvalidChoice>;
     fn recv_choice(&mut self) -> Result<bool, Self::RecvChoiceErr>;
+
+    /// Send a run of choices, for example to skip several branches of a
+    /// choose list at once. Carriers which are able to transfer a run in a
+    /// single message should override it, by default every choice is sent
+    /// separately. The peer may receive the run choice by choice.
+    fn send_choice_n(&mut self, choices: &[bool]) -> Result<(), Self::SendChoiceErr> {
+        for &choice in choices {
+            self.send_choice(choice)?;
+        }
+        Ok(())
+    }
+
+    /// Receive a run of `count` choices, whether the peer has sent them
+    /// separately or as a run.
+    fn recv_choice_n(&mut self, count: usize) -> Result<Vec<bool>, Self::RecvChoiceErr> {
+        (0 .. count).map(|_| self.recv_choice()).collect()
+    }
+
+    /// A development aid for carrier implementations. In debug builds it is
+    /// called after every `send`, `recv`, choice and offer step with the
+    /// name of the protocol the channel has reached, and should assert that
+    /// the internal state of the carrier is consistent with it (for example
+    /// that no choice bits are left unread before a value). Does nothing by
+    /// default.
+    fn debug_check_state(&self, _protocol: &'static str) {}
+
+    /// Called after every step with the name of the protocol the step has
+    /// been performed on, for example to profile the latency of the steps
+    /// (see `profile`). Does nothing by default.
+    fn step_performed(&mut self, _protocol: &'static str) {}
+}
+
+/// Carriers which are able to express every error of a session step with
+/// a single type should implement `CarrierError`, so that protocol code
+/// could propagate errors of all the steps with `?`. Value types sent over
+/// such carriers should make their errors convertible into `Error` as well.
+pub trait CarrierError: Carrier {
+    type Error: From<Self::SendChoiceErr> + From<Self::RecvChoiceErr>;
+}
+
+/// Carriers which buffer outgoing data should implement `Flush` in order
+/// to let the protocol force pending data out to the peer, for example
+/// right before blocking on the response to a request.
+pub trait Flush {
+    type FlushErr;
+
+    fn flush(&mut self) -> Result<(), Self::FlushErr>;
+}
+
+/// Buffering carriers which are able to flush pending output by themselves
+/// right before every blocking receive (of a value or of a choice) should
+/// implement `AutoFlush`. This rules out the deadlock where a request sits
+/// in the write buffer while its sender waits for the response. A failed
+/// flush is reported as an error of the receive.
+pub trait AutoFlush: Flush {
+    fn set_auto_flush(&mut self, enabled: bool);
+}
+
+/// Carriers which are able to bound their blocking operations in time
+/// should implement `Deadline`.
+pub trait Deadline {
+    fn set_deadline(&mut self, deadline: Option<Instant>);
+}
+
+/// Carriers which are able to wait for the next choice of the peer for a
+/// limited time should implement `PollChoice`.
+pub trait PollChoice: Carrier {
+    /// Wait at most `timeout` for the peer to make its next choice. Returns
+    /// `Ok(false)` if it has not arrived in time. The choice must not be
+    /// lost once it has arrived: the following `recv_choice` returns it
+    /// without blocking.
+    fn poll_choice(&mut self, timeout: Duration) -> Result<bool, Self::RecvChoiceErr>;
+}
+
+/// Carriers which are able to bound every single blocking operation in
+/// time should implement `DefaultTimeout`.
+pub trait DefaultTimeout {
+    fn set_default_timeout(&mut self, timeout: Option<Duration>);
+}
+
+/// Carriers which are able to fail a session left idle for too long should
+/// implement `IdleTimeout`. Unlike `DefaultTimeout`, which bounds each
+/// operation separately, the idle time is counted from the last successful
+/// operation, so time spent between operations counts as well. Unlike
+/// `Deadline`, the limit moves forward with every operation.
+pub trait IdleTimeout: Carrier {
+    fn set_idle_timeout(&mut self, timeout: Option<Duration>);
+
+    /// Whether a failed receive of a choice is due to the idle timeout.
+    fn is_idle_timeout(err: &Self::RecvChoiceErr) -> bool;
+}
+
+/// Carriers with bounded send buffers should implement `SendPressure`, so
+/// that a producer is able to throttle itself before a send blocks.
+pub trait SendPressure: Carrier {
+    /// How full the send buffer is, from `0.0` (empty) to `1.0` (full).
+    fn send_pressure(&self) -> f32;
+}
+
+/// What a carrier knows about the authenticated peer of a session, for
+/// example the subject of a client certificate or the claims of a token,
+/// as a map of string claims.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct PeerContext {
+    claims: BTreeMap<String, String>,
+}
+
+impl PeerContext {
+    pub fn new() -> PeerContext {
+        PeerContext::default()
+    }
+
+    pub fn with_claim<K, V>(mut self, key: K, value: V) -> PeerContext where K: Into<String>, V: Into<String> {
+        self.claims.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn claim(&self, key: &str) -> Option<&str> {
+        self.claims.get(key).map(|value| &value[..])
+    }
+
+    pub fn claims(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.claims.iter().map(|(key, value)| (&key[..], &value[..]))
+    }
+}
+
+/// Carriers which authenticate the peer when the session is established
+/// should implement `PeerIdentity` to expose the outcome to the protocol
+/// handlers.
+pub trait PeerIdentity: Carrier {
+    /// `None` if the peer has not been authenticated.
+    fn peer_context(&self) -> Option<&PeerContext>;
+}
+
+/// Carriers which are able to open two independent sub-carriers to the
+/// same peer should implement `Split`. Both endpoints split their carriers
+/// at the same protocol point, and the first (second) sub-carrier of one
+/// endpoint is connected to the first (second) sub-carrier of the other.
+pub trait Split: Sized {
+    type SplitErr;
+
+    fn split(&mut self) -> Result<(Self, Self), Self::SplitErr>;
+}
+
+/// Carriers which are able to transfer branch tags should implement `Tagged`
+/// in order to support `TaggedChoose` and `TaggedOffer`.
+///
+/// A peer may send a tag which is not offered, so `RecvTagErr` should be
+/// able to report it as `InvalidChoice`.
+pub trait Tagged: Carrier {
+    type SendTagErr;
+    fn send_tag(&mut self, tag: u64) -> Result<(), Self::SendTagErr>;
+
+    type RecvTagErr: From<InvalidChoice>;
+    fn recv_tag(&mut self) -> Result<u64, Self::RecvTagErr>;
+}
+
+/// A stable wire identifier of a `TaggedChoose` / `TaggedOffer` branch.
+///
+/// Tags must be unique within a branch list and must never be reused for a
+/// branch with a different meaning, so that peers built against different
+/// versions of a protocol agree on what every branch means.
+pub trait BranchTag {
+    const TAG: u64;
 }
 
 /// A session for a session typed channel.
@@ -41,10 +294,22 @@ pub struct Session<E, P>(PhantomData<(E, P)>);
 /// `SR` is the carrier channel for actual sending and receiving
 /// `P` is the protocol
 /// `E` is the environment, containing potential recursion targets
-#[must_use]
+#[must_use = "session channels must reach End and be closed"]
 pub struct Chan<SR, E, P> {
     carrier: SR,
     session: Session<E, P>,
+    guard: Option<Guard>,
+}
+
+/// Cleanup attached to a channel with `Chan::guard`, run when it is dropped.
+struct Guard(Option<Box<dyn FnOnce() + std::marker::Send>>);
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if let Some(on_drop) = self.0.take() {
+            on_drop();
+        }
+    }
 }
 
 /// Peano numbers: Zero
@@ -64,6 +329,28 @@ pub struct Recv<A, P>(PhantomData<(A, P)>);
 /// Send `A`, then `P`
 pub struct Send<A, P>(PhantomData<(A, P)>);
 
+/// Send a batch of `A` items in a single message, then `P`
+pub struct SendBatch<A, P>(PhantomData<(A, P)>);
+
+/// Receive a batch of `A` items in a single message, then `P`
+pub struct RecvBatch<A, P>(PhantomData<(A, P)>);
+
+/// Send a header `H` along with `A` in a single message, then `P`
+pub struct SendWithHeader<H, A, P>(PhantomData<(H, A, P)>);
+
+/// Receive a header `H` along with `A` in a single message, then `P`
+pub struct RecvWithHeader<H, A, P>(PhantomData<(H, A, P)>);
+
+/// Send either an `A` or nothing, as decided at run time, then `P`
+pub struct SendOpt<A, P>(PhantomData<(A, P)>);
+
+/// Receive either an `A` or nothing, as decided by the peer, then `P`
+pub struct RecvOpt<A, P>(PhantomData<(A, P)>);
+
+/// Run `P` and `Q` in parallel on independent sub-channels, then
+/// continue with `R` when both of them are complete
+pub struct Par<P, Q, R>(PhantomData<(P, Q, R)>);
+
 /// End of a list
 #[allow(missing_copy_implementations)]
 pub struct Nil;
@@ -74,6 +361,14 @@ pub struct Choose<P, L>(PhantomData<(P, L)>);
 /// Passive choice (offer) between `P` and protocols in the list `L`
 pub struct Offer<P, L>(PhantomData<(P, L)>);
 
+/// Active choice between `P` and `L`, selected on the wire by the tag `T`
+/// of the branch rather than by its position
+pub struct TaggedChoose<T, P, L>(PhantomData<(T, P, L)>);
+
+/// Passive choice (offer) between `P` and `L`, matched by the tag `T` of
+/// the branch rather than by its position
+pub struct TaggedOffer<T, P, L>(PhantomData<(T, P, L)>);
+
 /// Enter a recursive environment
 pub struct Rec<P>(PhantomData<P>);
 
@@ -81,6 +376,34 @@ pub struct Rec<P>(PhantomData<P>);
 /// out of.
 pub struct Var<N>(PhantomData<N>);
 
+/// An outcome of a single iteration of a recursive session, see `Chan::serve`.
+pub enum Loop<C, T> {
+    /// Go on with the next iteration using the recursed channel `C`.
+    Continue(C),
+    /// Leave the recursion with the result `T`.
+    Break(T),
+}
+
+/// Send either `Ok` value `T` and continue with `POk`, or `Err` value `F`
+/// and continue with `PErr`
+pub type SendResult<T, F, POk, PErr> = Choose<Send<T, POk>, Choose<Send<F, PErr>, Nil>>;
+
+/// Receive either `Ok` value `T` and continue with `POk`, or `Err` value `F`
+/// and continue with `PErr`
+pub type RecvResult<T, F, POk, PErr> = Offer<Recv<T, POk>, Offer<Recv<F, PErr>, Nil>>;
+
+/// Every session type has a dual: the protocol the opposite endpoint follows.
+///
+/// # Safety
+///
+/// `Dual` must be the exact mirror of the implementing protocol (every `Send`
+/// replaced by `Recv`, every `Choose` by `Offer` and vice versa), otherwise
+/// the endpoints desynchronize and the carrier receives values of unexpected
+/// types.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not a session protocol",
+    note = "protocols are built from `End`, `Send`, `Recv`, `SendBatch`, `RecvBatch`, `SendWithHeader`, `RecvWithHeader`, `SendOpt`, `RecvOpt`, `Par`, `Choose`, `Offer`, `Rec` and `Var`, with `Nil` terminating choice lists",
+)]
 pub unsafe trait HasDual {
     type Dual;
 }
@@ -97,6 +420,34 @@ unsafe impl<A, P: HasDual> HasDual for Recv<A, P> {
     type Dual = Send<A, P::Dual>;
 }
 
+unsafe impl<A, P: HasDual> HasDual for SendBatch<A, P> {
+    type Dual = RecvBatch<A, P::Dual>;
+}
+
+unsafe impl<A, P: HasDual> HasDual for RecvBatch<A, P> {
+    type Dual = SendBatch<A, P::Dual>;
+}
+
+unsafe impl<H, A, P: HasDual> HasDual for SendWithHeader<H, A, P> {
+    type Dual = RecvWithHeader<H, A, P::Dual>;
+}
+
+unsafe impl<H, A, P: HasDual> HasDual for RecvWithHeader<H, A, P> {
+    type Dual = SendWithHeader<H, A, P::Dual>;
+}
+
+unsafe impl<A, P: HasDual> HasDual for SendOpt<A, P> {
+    type Dual = RecvOpt<A, P::Dual>;
+}
+
+unsafe impl<A, P: HasDual> HasDual for RecvOpt<A, P> {
+    type Dual = SendOpt<A, P::Dual>;
+}
+
+unsafe impl<P: HasDual, Q: HasDual, R: HasDual> HasDual for Par<P, Q, R> {
+    type Dual = Par<P::Dual, Q::Dual, R::Dual>;
+}
+
 unsafe impl HasDual for Nil {
     type Dual = Nil;
 }
@@ -117,21 +468,577 @@ unsafe impl<N> HasDual for Var<S<N>> {
     type Dual = Var<S<N>>;
 }
 
+unsafe impl<T, P: HasDual, L: HasDual> HasDual for TaggedChoose<T, P, L> {
+    type Dual = TaggedOffer<T, P::Dual, L::Dual>;
+}
+
+unsafe impl<T, P: HasDual, L: HasDual> HasDual for TaggedOffer<T, P, L> {
+    type Dual = TaggedChoose<T, P::Dual, L::Dual>;
+}
+
 unsafe impl<P: HasDual> HasDual for Rec<P> {
     type Dual = Rec<P::Dual>;
 }
 
+/// `Self` is the dual protocol of `P`: the two ends of a session typed
+/// channel must be duals of each other. It holds for any protocol with a
+/// `HasDual` implementation, including user defined ones:
+///
+/// ```
+/// use session_types_ng::*;
+///
+/// // A protocol step of its own, along with its dual
+/// struct Ping;
+/// struct Pong;
+///
+/// unsafe impl HasDual for Ping {
+///     type Dual = Pong;
+/// }
+///
+/// unsafe impl HasDual for Pong {
+///     type Dual = Ping;
+/// }
+///
+/// // Custom steps are driven by code of their own, these ones do nothing
+/// fn ping(chan: Chan<mpsc::Channel, (), Ping>) {
+///     unsafe { chan.cast_raw::<(), End>() }.close();
+/// }
+///
+/// fn pong(chan: Chan<mpsc::Channel, (), Pong>) {
+///     unsafe { chan.cast_raw::<(), End>() }.close();
+/// }
+///
+/// mpsc::connect(ping, pong);
+/// ```
+#[diagnostic::on_unimplemented(
+    message = "session protocol `{Self}` is not the dual of `{P}`",
+    label = "this endpoint does not match the other end of the session",
+    note = "the protocol of one endpoint must be `<P as HasDual>::Dual` of the other: every `Send` must face a `Recv`, every `Choose` an `Offer`, with the same payload types",
+)]
+pub trait DualOf<P> {}
+
+impl<P, Q> DualOf<P> for Q where P: HasDual<Dual = Q> {}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Protocols which are guaranteed to communicate before they recurse:
+/// every path through a productive protocol performs a `Send`, a `Recv`
+/// (or their batch forms), a choice or a `Par` split, or reaches `End`,
+/// before it reaches a `Var`. A choice transfers data on its own, so the
+/// branches of `Choose` and `Offer` may recurse right away, to any depth.
+///
+/// Entering a recursion is only allowed for productive protocols, so an
+/// unproductive loop, which could only spin or deadlock at runtime, is
+/// rejected at compile time:
+///
+/// ```compile_fail
+/// use session_types_ng::*;
+///
+/// fn spin(chan: Chan<mpsc::Channel, (), Rec<Var<Z>>>) {
+///     let _ = chan.enter();
+/// }
+/// ```
+///
+/// The check only looks at the body of each `Rec` on its own: a nested
+/// `Rec` whose body jumps straight to an outer loop is rejected, even if
+/// the outer loop has communicated on its way there. The inner `Rec` of
+/// `Rec<Send<A, Rec<Var<S<Z>>>>>` cannot be entered, such a loop is
+/// written as `Rec<Send<A, Var<Z>>>` instead:
+///
+/// ```compile_fail
+/// use session_types_ng::*;
+///
+/// fn nested(chan: Chan<mpsc::Channel, (), Rec<Send<mpsc::Value<u8>, Rec<Var<S<Z>>>>>>) {
+///     let _ = chan.enter().send(mpsc::Value(0)).unwrap().enter();
+/// }
+/// ```
+pub trait Productive: sealed::Sealed {}
+
+impl sealed::Sealed for End {}
+impl<A, P> sealed::Sealed for Send<A, P> {}
+impl<A, P> sealed::Sealed for Recv<A, P> {}
+impl<A, P> sealed::Sealed for SendBatch<A, P> {}
+impl<H, A, P> sealed::Sealed for SendWithHeader<H, A, P> {}
+impl<H, A, P> sealed::Sealed for RecvWithHeader<H, A, P> {}
+impl<A, P> sealed::Sealed for SendOpt<A, P> {}
+impl<A, P> sealed::Sealed for RecvOpt<A, P> {}
+impl<A, P> sealed::Sealed for RecvBatch<A, P> {}
+impl<P, Q, R> sealed::Sealed for Par<P, Q, R> {}
+impl sealed::Sealed for Nil {}
+impl<P, L> sealed::Sealed for Choose<P, L> {}
+impl<P, L> sealed::Sealed for Offer<P, L> {}
+impl<T, P, L> sealed::Sealed for TaggedChoose<T, P, L> {}
+impl<T, P, L> sealed::Sealed for TaggedOffer<T, P, L> {}
+impl<N> sealed::Sealed for Var<N> {}
+impl<P> sealed::Sealed for Rec<P> {}
+
+impl Productive for End {}
+impl<A, P> Productive for Send<A, P> {}
+impl<A, P> Productive for Recv<A, P> {}
+impl<A, P> Productive for SendBatch<A, P> {}
+impl<H, A, P> Productive for SendWithHeader<H, A, P> {}
+impl<H, A, P> Productive for RecvWithHeader<H, A, P> {}
+impl<A, P> Productive for SendOpt<A, P> {}
+impl<A, P> Productive for RecvOpt<A, P> {}
+impl<A, P> Productive for RecvBatch<A, P> {}
+impl<P, Q, R> Productive for Par<P, Q, R> {}
+impl Productive for Nil {}
+impl<P, L> Productive for Choose<P, L> {}
+impl<P, L> Productive for Offer<P, L> {}
+impl<T, P, L> Productive for TaggedChoose<T, P, L> {}
+impl<T, P, L> Productive for TaggedOffer<T, P, L> {}
+impl<P: Productive> Productive for Rec<P> {}
+
+/// Branch lists of a `Choose` which have `M` as a prefix: `M` keeps the
+/// first branches of `Self` in the same order and drops the rest.
+///
+/// Choices are encoded by position, so choosing a branch of the prefix puts
+/// exactly the same bits on the wire as choosing it from the full list.
+pub trait ChoosePrefix<M> {}
+
+impl ChoosePrefix<Nil> for Nil {}
+impl<P, L> ChoosePrefix<Nil> for Choose<P, L> {}
+impl<P, L, M> ChoosePrefix<Choose<P, M>> for Choose<P, L> where L: ChoosePrefix<M> {}
+
 impl<E, P> Drop for Session<E, P> {
     fn drop(&mut self) {
-        panic!("Session prematurely dropped");
+        // Do not turn a panic which is already unwinding through a session into an abort
+        if !std::thread::panicking() {
+            panic!("Session prematurely dropped at protocol state {}", type_name::<P>());
+        }
     }
 }
 
 impl<SR, E, P> Chan<SR, E, P> {
     pub fn new(carrier: SR) -> Chan<SR, E, P> {
         Chan {
-            carrier: carrier,
+            carrier,
+            session: Session(PhantomData),
+            guard: None,
+        }
+    }
+
+    /// Attach `on_drop` to the session, to be run once it is over however
+    /// it ends: closed, aborted by an error of a step, or dropped while
+    /// unwinding a panic. For example, to release a resource the handler
+    /// of the session holds. Guards attached later run first.
+    ///
+    /// The closure has to be `Send` so that the channel stays `Send`.
+    /// The channels of sub-sessions opened by `par` do not inherit it. The
+    /// guard runs when the carrier is taken out of the channel by
+    /// `shutdown`, while `restart` and `upgrade` keep it for the session
+    /// carrying on over the same connection.
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use session_types_ng::*;
+    ///
+    /// let active = Arc::new(AtomicUsize::new(1));
+    /// // A peer which goes away right away
+    /// let (srv, cli) = mpsc::session_channel::<End>();
+    /// cli.close();
+    /// let srv: Chan<_, (), Recv<mpsc::Value<u8>, End>> = Chan::new(srv.shutdown());
+    /// let srv = {
+    ///     let active = active.clone();
+    ///     srv.guard(move || { active.fetch_sub(1, Ordering::SeqCst); })
+    /// };
+    /// assert!(srv.recv_value().is_err());
+    /// assert_eq!(active.load(Ordering::SeqCst), 0);
+    /// ```
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn guard<F>(mut self, on_drop: F) -> Chan<SR, E, P> where F: FnOnce() + std::marker::Send + 'static {
+        let outer = self.guard.take();
+        self.guard = Some(Guard(Some(Box::new(move || {
+            on_drop();
+            drop(outer);
+        }))));
+        self
+    }
+
+    /// Returns the name of the current protocol state `P`, for example
+    /// `Recv<i64, Send<i64, Var<Z>>>`. Useful for diagnostics only.
+    pub fn probe_type_name(&self) -> &'static str {
+        type_name::<P>()
+    }
+
+    /// Capture the position of the session, to be persisted and resumed
+    /// later with `resume`. See `SessionToken`.
+    pub fn position(&self) -> SessionToken {
+        SessionToken {
+            protocol: type_name::<P>().to_string(),
+            env: type_name::<E>().to_string(),
+        }
+    }
+
+    /// Resume a session at the position captured by `token` over a carrier
+    /// which has been re-established to the same point of the session, e.g.
+    /// after a restart of the process. The carrier is handed back when the
+    /// token has been captured at a different position.
+    ///
+    /// Both peers must resume at the same point with dual protocols (this
+    /// is not checked).
+    ///
+    /// ```
+    /// use session_types_ng::*;
+    /// use session_types_ng::mpsc::Value;
+    ///
+    /// type Wizard = Recv<Value<String>, Recv<Value<u8>, End>>;
+    ///
+    /// let (srv, cli) = mpsc::session_channel::<Wizard>();
+    /// let cli = cli.send_value("alice".to_string()).unwrap();
+    /// let (srv, _name) = srv.recv_value().unwrap();
+    /// let saved = srv.position().to_string();
+    /// # std::mem::forget((srv, cli));
+    ///
+    /// // Later on, over a fresh connection to the same peer
+    /// let token: SessionToken = saved.parse().unwrap();
+    /// let (carrier, peer) = mpsc::session_channel::<End>();
+    /// let srv = Chan::<_, (), Recv<Value<u8>, End>>::resume(carrier.shutdown(), &token).ok().unwrap();
+    /// let cli = Chan::<_, (), Send<Value<u8>, End>>::new(peer.shutdown());
+    /// cli.send_value(42).unwrap().close();
+    /// let (srv, age) = srv.recv_value().unwrap();
+    /// srv.close();
+    /// assert_eq!(age, 42);
+    /// ```
+    pub fn resume(carrier: SR, token: &SessionToken) -> Result<Chan<SR, E, P>, SR> {
+        if token.protocol == type_name::<P>() && token.env == type_name::<E>() {
+            Ok(Chan::new(carrier))
+        } else {
+            Err(carrier)
+        }
+    }
+
+    /// Continue the session over the carrier built by `f` from the current
+    /// one, for example to wrap a plaintext connection in an encrypted one
+    /// once a STARTTLS-style handshake has been performed over it.
+    ///
+    /// Both peers must upgrade at exactly the same protocol step and before
+    /// the next one, since anything exchanged between the two upgrades
+    /// would be read by the wrong carrier. This is not checked: put the
+    /// upgrade right after a step which both peers have completed, such as
+    /// the receive of the handshake reply on one side and the send of it on
+    /// the other one. The values of the rest of the protocol must be ones
+    /// sent over the new carrier.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn upgrade<C2, F>(self, f: F) -> Chan<C2, E, P> where F: FnOnce(SR) -> C2 {
+        std::mem::forget(self.session);
+        Chan {
+            carrier: f(self.carrier),
             session: Session(PhantomData),
+            guard: self.guard,
+        }
+    }
+}
+
+/// The position of a session: the names of its protocol state and of its
+/// recursion environment. The textual form is `protocol@env`, where any
+/// `@` or `\` inside of the names is escaped with a `\`.
+///
+/// Type names are not guaranteed to be stable across compiler versions or
+/// builds, so a token should only be resumed by the same build of the
+/// program which has captured it.
+///
+/// ```
+/// use session_types_ng::*;
+/// use session_types_ng::mpsc::Value;
+///
+/// type Upload = Rec<Offer<End, Offer<Recv<Value<Vec<(u8, String)>>, Var<Z>>, Nil>>>;
+///
+/// let (srv, cli) = mpsc::session_channel::<Upload>();
+/// let srv = srv.enter();
+/// let token = srv.position();
+/// let parsed: SessionToken = token.to_string().parse().unwrap();
+/// assert_eq!(parsed, token);
+/// # std::mem::forget((srv, cli));
+///
+/// let token: SessionToken = r"Mail<user\@host>@()".parse().unwrap();
+/// assert_eq!(token.protocol(), "Mail<user@host>");
+/// assert_eq!(token.to_string(), r"Mail<user\@host>@()");
+/// ```
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct SessionToken {
+    protocol: String,
+    env: String,
+}
+
+impl SessionToken {
+    pub fn protocol(&self) -> &str {
+        &self.protocol
+    }
+
+    pub fn env(&self) -> &str {
+        &self.env
+    }
+}
+
+impl fmt::Display for SessionToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let escape = |name: &str| name.replace('\\', "\\\\").replace('@', "\\@");
+        write!(f, "{}@{}", escape(&self.protocol), escape(&self.env))
+    }
+}
+
+/// A textual token which is not of the `protocol@env` form.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct InvalidSessionToken;
+
+impl std::str::FromStr for SessionToken {
+    type Err = InvalidSessionToken;
+
+    fn from_str(s: &str) -> Result<SessionToken, InvalidSessionToken> {
+        // Type names may contain `@` as well, so it only separates them unescaped
+        let mut protocol = String::new();
+        let mut env = None;
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            let c = match c {
+                '\\' => match chars.next() {
+                    Some(c @ '\\') | Some(c @ '@') => c,
+                    _ => return Err(InvalidSessionToken),
+                },
+                '@' if env.is_none() => {
+                    env = Some(String::new());
+                    continue;
+                },
+                '@' => return Err(InvalidSessionToken),
+                c => c,
+            };
+            env.as_mut().unwrap_or(&mut protocol).push(c);
+        }
+        env.map(|env| SessionToken { protocol, env }).ok_or(InvalidSessionToken)
+    }
+}
+
+/// Environment stacks of any depth: the number of enclosing `Rec`s.
+pub trait EnvDepth {
+    const DEPTH: usize;
+}
+
+impl EnvDepth for () {
+    const DEPTH: usize = 0;
+}
+
+impl<P, E> EnvDepth for (P, E) where E: EnvDepth {
+    const DEPTH: usize = E::DEPTH + 1;
+}
+
+/// Prints the protocol state and the number of enclosing `Rec`s only, so
+/// that neither the carrier nor the protocol types have to be `Debug`.
+///
+/// ```
+/// use session_types_ng::*;
+///
+/// let (srv, cli) = mpsc::session_channel::<Rec<Recv<mpsc::Value<u8>, Var<Z>>>>();
+/// let srv = srv.enter();
+/// assert!(format!("{:?}", srv).contains("depth: 1"));
+/// # std::mem::forget((srv, cli));
+/// ```
+impl<SR, E, P> fmt::Debug for Chan<SR, E, P> where E: EnvDepth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Chan")
+            .field("protocol", &type_name::<P>())
+            .field("depth", &E::DEPTH)
+            .finish()
+    }
+}
+
+/// Returns two session channels over a pair of interconnected carriers
+/// built by `make`: the first one follows protocol `P` and the second one
+/// follows its dual.
+#[must_use = "session channels must reach End and be closed"]
+pub fn session_channel_with<P, SA, SB, F>(make: F) -> (Chan<SA, (), P>, Chan<SB, (), P::Dual>)
+    where P: HasDual, F: FnOnce() -> (SA, SB)
+{
+    let (master_carrier, slave_carrier) = make();
+    (Chan::new(master_carrier),
+     Chan::new(slave_carrier))
+}
+
+/// Start a session following protocol `P` over a carrier established by
+/// `connect`, trying it up to `attempts` times in total (at least once) with `delay`
+/// between the attempts. Returns the error of the last attempt if all of
+/// them have failed.
+///
+/// Only establishing the carrier is retried: a failure in the middle of
+/// the session still fails the session, because replaying protocol steps
+/// which the peer might have already acted upon is unsafe unless the whole
+/// protocol is idempotent.
+pub fn connect_with_retry<P, SR, Err, F>(attempts: usize, delay: Duration, mut connect: F) -> Result<Chan<SR, (), P>, Err>
+    where P: HasDual, F: FnMut() -> Result<SR, Err>
+{
+    let mut attempt = 1;
+    loop {
+        match connect() {
+            Ok(carrier) =>
+                return Ok(Chan::new(carrier)),
+            Err(e) if attempt >= attempts =>
+                return Err(e),
+            Err(_) => {
+                attempt += 1;
+                std::thread::sleep(delay);
+            },
+        }
+    }
+}
+
+impl<SR, E, P> Chan<SR, E, P> where SR: Flush {
+    /// Flush the underlying carrier. Returns the same channel with
+    /// unchanged protocol `P`.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn flush(mut self) -> Result<Chan<SR, E, P>, SR::FlushErr> {
+        match self.carrier.flush() {
+            Ok(()) =>
+                Ok(self),
+            Err(e) => {
+                close_chan(self);
+                Err(e)
+            },
+        }
+    }
+}
+
+impl<SR, E, P> Chan<SR, E, P> where SR: AutoFlush {
+    /// Make the carrier flush pending output before every blocking receive
+    /// for the rest of the session, so that no explicit `flush` is needed
+    /// in request/response protocols.
+    ///
+    /// This trades batching for safety: consecutive sends followed by a
+    /// receive still share a flush, but a protocol which alternates sends
+    /// and receives pays for a flush on every turn, even where the peer
+    /// would not have waited for the data.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn auto_flush_before_recv(mut self) -> Chan<SR, E, P> {
+        self.carrier.set_auto_flush(true);
+        self
+    }
+}
+
+impl<SR, E, P> Chan<SR, E, P> where SR: Deadline {
+    /// Set a deadline for the rest of the session: every blocking receive
+    /// (of a value or of an offered choice) fails with a carrier error once
+    /// the `deadline` has passed.
+    ///
+    /// Note that the deadline only bounds receiving. A send which blocks
+    /// (for example on a full bounded carrier) is not interrupted by it.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn with_deadline(mut self, deadline: Instant) -> Chan<SR, E, P> {
+        self.carrier.set_deadline(Some(deadline));
+        self
+    }
+}
+
+impl<SR, E, P> Chan<SR, E, P> where SR: IdleTimeout {
+    /// Fail the next blocking operation once the channel has been idle for
+    /// longer than `timeout`, or remove the limit with `None`. The idle
+    /// timer starts now and restarts after every successful operation.
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.carrier.set_idle_timeout(timeout);
+    }
+}
+
+impl<SR, E, P> Chan<SR, E, P> where SR: SendPressure {
+    /// How full the send buffer of the carrier is, from `0.0` (empty) to
+    /// `1.0` (full), for example for a streaming producer to check between
+    /// sends. The value is advisory: it may be stale by the time of the
+    /// next send, which may block at any pressure.
+    pub fn send_pressure(&self) -> f32 {
+        self.carrier.send_pressure()
+    }
+}
+
+impl<SR, E, P> Chan<SR, E, P> where SR: PeerIdentity {
+    /// The authenticated identity of the peer, for authorization decisions
+    /// in the protocol handlers.
+    pub fn peer_context(&self) -> Option<&PeerContext> {
+        self.carrier.peer_context()
+    }
+}
+
+impl<SR, E, P> Chan<SR, E, P> where SR: DefaultTimeout {
+    /// Set a timeout applied to every following blocking operation on the
+    /// channel separately, or remove it with `None`. An operation which
+    /// takes longer fails with a carrier error.
+    pub fn set_default_timeout(&mut self, timeout: Option<Duration>) {
+        self.carrier.set_default_timeout(timeout);
+    }
+}
+
+/// An error of the runtime protocol check, see `Chan::check_protocol`.
+#[cfg(feature = "debug_protocol_check")]
+#[derive(Debug)]
+pub enum ProtocolCheckError<SE, RE, FE> {
+    Send(SE),
+    Recv(RE),
+    Flush(FE),
+    /// The peer follows a protocol which is not the dual of ours.
+    Mismatch {
+        expected: &'static str,
+        expected_tag: u64,
+        got_tag: u64,
+    },
+}
+
+/// FNV-1a hash of a protocol type name.
+///
+/// Type names are not guaranteed to be stable across compiler versions or
+/// builds, so only the tags computed by the same build of the program are
+/// comparable.
+#[cfg(feature = "debug_protocol_check")]
+fn protocol_tag(name: &str) -> u64 {
+    name.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+#[cfg(feature = "debug_protocol_check")]
+impl<SR, E, P> Chan<SR, E, P> where SR: Carrier + Flush, P: HasDual {
+    /// Exchange protocol tags with the peer and make sure it follows the
+    /// dual of protocol `P`. Both endpoints should perform the check at the
+    /// same protocol point, typically right after the channel is created.
+    ///
+    /// The check is meant for the ends which are built separately, over a
+    /// carrier connecting them by other means: the ends created together by
+    /// `session_channel` always match. The tag is a hash of the protocol
+    /// type name, so both peers should be the same build of the program.
+    ///
+    /// ```
+    /// use std::thread::spawn;
+    /// use session_types_ng::*;
+    ///
+    /// let (a, b) = mpsc::session_channel::<End>();
+    /// let a: Chan<_, (), Send<mpsc::Value<u8>, End>> = Chan::new(a.shutdown());
+    /// let b: Chan<_, (), Recv<mpsc::Value<u16>, End>> = Chan::new(b.shutdown());
+    ///
+    /// let peer = spawn(move || b.check_protocol().is_err());
+    /// assert!(a.check_protocol().is_err());
+    /// assert!(peer.join().unwrap());
+    /// ```
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn check_protocol(mut self) -> Result<Chan<SR, E, P>, ProtocolCheckError<SR::SendChoiceErr, SR::RecvChoiceErr, SR::FlushErr>> {
+        let own_tag = protocol_tag(type_name::<P>());
+        let expected = type_name::<P::Dual>();
+        let expected_tag = protocol_tag(expected);
+
+        // The tag travels as a single run of choices
+        let own_bits: Vec<bool> = (0 .. 64).map(|bit| own_tag & (1 << bit) != 0).collect();
+        let result = self.carrier.send_choice_n(&own_bits).map_err(ProtocolCheckError::Send)
+            .and_then(|()| self.carrier.flush().map_err(ProtocolCheckError::Flush))
+            .and_then(|()| self.carrier.recv_choice_n(64).map_err(ProtocolCheckError::Recv))
+            .map(|bits| bits.iter().enumerate().fold(0, |tag, (bit, &choice)| if choice { tag | (1 << bit) } else { tag }))
+            .and_then(|got_tag| if got_tag == expected_tag {
+                Ok(())
+            } else {
+                Err(ProtocolCheckError::Mismatch { expected, expected_tag, got_tag, })
+            });
+
+        match result {
+            Ok(()) =>
+                Ok(self),
+            Err(e) => {
+                close_chan(self);
+                Err(e)
+            },
         }
     }
 }
@@ -144,16 +1051,62 @@ impl<SR, E> Chan<SR, E, End> {
         close_chan(self);
     }
 
+    /// Close the channel and return `value`, so that a handler could end
+    /// the session and produce its result in a single expression.
+    pub fn close_with<T>(self, value: T) -> T {
+        close_chan(self);
+        value
+    }
+
     /// Same as `close`, but keep underlying carrier alive.
     pub fn shutdown(self) -> SR {
         std::mem::forget(self.session);
         self.carrier
     }
+
+    /// Reuse the carrier for a fresh session following protocol `P2`, for
+    /// example to pipeline several requests over one connection.
+    ///
+    /// Both peers must restart at the same point with dual protocols (this
+    /// is not checked), and the carrier must not hold anything left over
+    /// from the finished session.
+    ///
+    /// The connection carries on, and so does the guard attached with
+    /// `guard`: it runs once the last session over the carrier is over.
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use session_types_ng::*;
+    ///
+    /// let closed = Arc::new(AtomicUsize::new(0));
+    /// let (srv, cli) = mpsc::session_channel::<Recv<mpsc::Value<u8>, End>>();
+    /// let srv = {
+    ///     let closed = closed.clone();
+    ///     srv.guard(move || { closed.fetch_add(1, Ordering::SeqCst); })
+    /// };
+    ///
+    /// let cli = cli.send_value(1).unwrap().restart::<Send<mpsc::Value<u8>, End>>();
+    /// let (srv, _) = srv.recv_value().unwrap();
+    /// let srv = srv.restart::<Recv<mpsc::Value<u8>, End>>();
+    /// assert_eq!(closed.load(Ordering::SeqCst), 0);
+    ///
+    /// cli.send_value(2).unwrap().close();
+    /// let (srv, n) = srv.recv_value().unwrap();
+    /// srv.close();
+    /// assert_eq!(n, 2);
+    /// assert_eq!(closed.load(Ordering::SeqCst), 1);
+    /// ```
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn restart<P2: HasDual>(self) -> Chan<SR, (), P2> {
+        cast_chan(self)
+    }
 }
 
 fn close_chan<SR, E, P>(chan: Chan<SR, E, P>) {
     drop(chan.carrier);
     std::mem::forget(chan.session);
+    drop(chan.guard);
 }
 
 fn cast_chan<SR, EA, EB, PA, PB>(chan: Chan<SR, EA, PA>) -> Chan<SR, EB, PB> {
@@ -161,17 +1114,97 @@ fn cast_chan<SR, EA, EB, PA, PB>(chan: Chan<SR, EA, PA>) -> Chan<SR, EB, PB> {
     Chan {
         carrier: chan.carrier,
         session: Session(PhantomData),
+        guard: chan.guard,
+    }
+}
+
+/// `cast_chan` for a protocol step which has just been performed on the
+/// carrier: the carrier is notified of the step, and debug builds let it
+/// check its state against the protocol reached.
+fn step_chan<SR, EA, EB, PA, PB>(mut chan: Chan<SR, EA, PA>) -> Chan<SR, EB, PB> where SR: Carrier {
+    chan.carrier.step_performed(type_name::<PA>());
+    let chan = cast_chan(chan);
+    #[cfg(debug_assertions)]
+    chan.carrier.debug_check_state(type_name::<PB>());
+    chan
+}
+
+/// Send a run of choices navigating a choose list as a single step.
+fn choose_run<SR, E, PA, PB>(mut chan: Chan<SR, E, PA>, choices: &[bool]) -> Result<Chan<SR, E, PB>, SR::SendChoiceErr> where SR: Carrier {
+    match chan.carrier.send_choice_n(choices) {
+        Ok(()) =>
+            Ok(step_chan(chan)),
+        Err(e) => {
+            close_chan(chan);
+            Err(e)
+        },
+    }
+}
+
+impl<SR, E, P> Chan<SR, E, P> where SR: Carrier {
+    /// Send a raw `choice` over the carrier and continue as `Chan<SR, E2,
+    /// P2>`, for combinators navigating choices in ways the typed methods
+    /// do not cover. Closes the channel on failure.
+    ///
+    /// # Safety
+    ///
+    /// The same obligation as for every cast of a channel: the peer must
+    /// expect a choice right now and continue with the dual of `P2` in the
+    /// environment `E2` after receiving `choice`. Carriers are allowed to
+    /// rely on the protocol for the types of the values they transfer, e.g.
+    /// `mpsc::Channel` reinterprets what it receives as the type the
+    /// protocol names, so a wrong `P2` is undefined behaviour.
+    #[must_use = "session channels must reach End and be closed"]
+    pub unsafe fn send_choice_raw<E2, P2>(mut self, choice: bool) -> Result<Chan<SR, E2, P2>, SR::SendChoiceErr> {
+        match self.carrier.send_choice(choice) {
+            Ok(()) =>
+                Ok(step_chan(self)),
+            Err(e) => {
+                close_chan(self);
+                Err(e)
+            },
+        }
+    }
+
+    /// Receive a raw choice from the carrier. The returned channel still
+    /// follows `P`, and has to be moved to the protocol selected by the
+    /// choice with `cast_raw`. Closes the channel on failure.
+    ///
+    /// # Safety
+    ///
+    /// The peer must be sending a choice right now, see `send_choice_raw`.
+    #[must_use = "session channels must reach End and be closed"]
+    pub unsafe fn recv_choice_raw(mut self) -> Result<(Chan<SR, E, P>, bool), SR::RecvChoiceErr> {
+        match self.carrier.recv_choice() {
+            Ok(choice) =>
+                Ok((step_chan(self), choice)),
+            Err(e) => {
+                close_chan(self);
+                Err(e)
+            },
+        }
+    }
+
+    /// Continue as `Chan<SR, E2, P2>` without touching the carrier.
+    ///
+    /// # Safety
+    ///
+    /// The peer must be at the dual of `P2` in the environment `E2`, see
+    /// `send_choice_raw`.
+    #[must_use = "session channels must reach End and be closed"]
+    pub unsafe fn cast_raw<E2, P2>(self) -> Chan<SR, E2, P2> {
+        cast_chan(self)
     }
 }
 
 impl<SR, E, P, T> Chan<SR, E, Send<T, P>> where SR: Carrier, T: ChannelSend<Crr = SR> {
     /// Send a value of type `T` over the channel. Returns a channel with
     /// protocol `P`
-    #[must_use]
+    #[must_use = "session channels must reach End and be closed"]
     pub fn send(mut self, v: T) -> Result<Chan<SR, E, P>, T::Err> {
         match v.send(&mut self.carrier) {
             Ok(()) =>
-                Ok(cast_chan(self)),
+                Ok(step_chan(self)),
             Err(e) => {
                 close_chan(self);
                 Err(e)
@@ -180,50 +1213,276 @@ impl<SR, E, P, T> Chan<SR, E, Send<T, P>> where SR: Carrier, T: ChannelSend<Crr
     }
 }
 
+impl<SR, E, P, T> Chan<SR, E, Send<T, P>> where SR: Carrier, T: ChannelSendUnsent<Crr = SR> {
+    /// Same as `send`, but on failure the value which has not been sent is
+    /// returned instead of the error.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn try_send(self, v: T) -> Result<Chan<SR, E, P>, T> {
+        self.send(v).map_err(T::into_unsent)
+    }
+}
+
 impl<SR, E, P, T> Chan<SR, E, Recv<T, P>> where SR: Carrier, T: ChannelRecv<Crr = SR> {
     /// Receives a value of type `T` from the channel. Returns a tuple
     /// containing the resulting channel and the received value.
-    #[must_use]
+    #[must_use = "session channels must reach End and be closed"]
     pub fn recv(mut self) -> Result<(Chan<SR, E, P>, T), T::Err> {
         match <T as ChannelRecv>::recv(&mut self.carrier) {
             Ok(v) =>
-                Ok((cast_chan(self), v)),
+                Ok((step_chan(self), v)),
             Err(e) => {
                 close_chan(self);
                 Err(e)
             },
         }
     }
-}
 
-impl<SR, E, P, L> Chan<SR, E, Choose<P, L>> where SR: Carrier {
-    /// Perform an active choice, selecting protocol `P` (head of the choose list).
-    #[must_use]
-    pub fn car(mut self) -> Result<Chan<SR, E, P>, SR::SendChoiceErr> {
-        match self.carrier.send_choice(true) {
+    /// Same as `recv`, but falls back to a value produced by `f` when the
+    /// receive fails. The session is over in that case, so the channel is
+    /// closed and `None` is returned in its place.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn recv_or_else<F>(self, f: F) -> (Option<Chan<SR, E, P>>, T) where F: FnOnce() -> T {
+        match self.recv() {
+            Ok((chan, v)) =>
+                (Some(chan), v),
+            Err(_) =>
+                (None, f()),
+        }
+    }
+
+    /// Same as `recv`, but the received value is accepted only once
+    /// `validate` approves it. A rejected value aborts the session: the
+    /// channel is closed and the error of `validate` is returned.
+    ///
+    /// ```
+    /// use session_types_ng::*;
+    /// use session_types_ng::mpsc::Value;
+    ///
+    /// let (srv, cli) = mpsc::session_channel::<Recv<Value<String>, End>>();
+    /// cli.send_value(String::new()).unwrap().close();
+    /// match srv.recv_validated(|&Value(ref name)| if name.is_empty() { Err("empty name") } else { Ok(()) }) {
+    ///     Err(ValidationError::Invalid(e)) => assert_eq!(e, "empty name"),
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn recv_validated<V, F>(self, validate: F) -> Result<(Chan<SR, E, P>, T), ValidationError<V, T::Err>>
+        where F: FnOnce(&T) -> Result<(), V>
+    {
+        let (chan, v) = self.recv().map_err(ValidationError::Recv)?;
+        match validate(&v) {
             Ok(()) =>
-                Ok(cast_chan(self)),
+                Ok((chan, v)),
             Err(e) => {
-                close_chan(self);
-                Err(e)
+                close_chan(chan);
+                Err(ValidationError::Invalid(e))
             },
         }
     }
+}
 
-    /// alias to `car` method
-    #[must_use]
-    pub fn first(self) -> Result<Chan<SR, E, P>, SR::SendChoiceErr> {
-        self.car()
+impl<SR, E, P, Q, R> Chan<SR, E, Send<Q, Recv<R, P>>>
+    where SR: CarrierError + Flush,
+          Q: ChannelSend<Crr = SR>,
+          R: ChannelRecv<Crr = SR>,
+          SR::Error: From<Q::Err> + From<R::Err> + From<SR::FlushErr>
+{
+    /// Send the `request`, flush the carrier and receive the response in a
+    /// single call, so that a buffered request can never be left waiting
+    /// for a receive which blocks on it. Returns a tuple containing the
+    /// resulting channel and the response.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn call(self, request: Q) -> Result<(Chan<SR, E, P>, R), SR::Error> {
+        Ok(self.send(request)?.flush()?.recv()?)
     }
 }
 
-impl<SR, E, P, Q, L> Chan<SR, E, Choose<P, Choose<Q, L>>> where SR: Carrier {
-     /// Perform an active choice, skipping first element and selecting tail of the choose list.
-    #[must_use]
-    pub fn cdr(mut self) -> Result<Chan<SR, E, Choose<Q, L>>, SR::SendChoiceErr> {
+impl<SR, E, P, T> Chan<SR, E, SendBatch<T, P>> where SR: Carrier, T: ChannelSendBatch<Crr = SR> {
+    /// Send all the `items` over the channel in a single message. Returns a
+    /// channel with protocol `P`
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn send_batch(mut self, items: &[T::Item]) -> Result<Chan<SR, E, P>, T::Err> {
+        match T::send_batch(items, &mut self.carrier) {
+            Ok(()) =>
+                Ok(step_chan(self)),
+            Err(e) => {
+                close_chan(self);
+                Err(e)
+            },
+        }
+    }
+}
+
+impl<SR, E, P, T> Chan<SR, E, RecvBatch<T, P>> where SR: Carrier, T: ChannelRecvBatch<Crr = SR> {
+    /// Receives a batch of items sent in a single message. Returns a tuple
+    /// containing the resulting channel and the received items.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn recv_batch(mut self) -> Result<(Chan<SR, E, P>, Vec<T::Item>), T::Err> {
+        match T::recv_batch(&mut self.carrier) {
+            Ok(items) =>
+                Ok((step_chan(self), items)),
+            Err(e) => {
+                close_chan(self);
+                Err(e)
+            },
+        }
+    }
+}
+
+/// Protocols which can be relayed by a proxy step by step until the
+/// boundary protocol `B` is reached, see `Chan::splice_until`. `N` is the
+/// number of steps to relay, it is inferred by the compiler.
+pub trait SpliceUntil<SR, B, N>: HasDual + Sized where SR: CarrierError, B: HasDual {
+    fn splice<EA, EB>(near: Chan<SR, EA, Self>, far: Chan<SR, EB, Self::Dual>) ->
+        Result<(Chan<SR, EA, B>, Chan<SR, EB, B::Dual>), SR::Error>;
+}
+
+impl<SR, B> SpliceUntil<SR, B, Z> for B where SR: CarrierError, B: HasDual {
+    fn splice<EA, EB>(near: Chan<SR, EA, B>, far: Chan<SR, EB, B::Dual>) ->
+        Result<(Chan<SR, EA, B>, Chan<SR, EB, B::Dual>), SR::Error>
+    {
+        Ok((near, far))
+    }
+}
+
+impl<SR, B, N, T, P> SpliceUntil<SR, B, S<N>> for Recv<T, P>
+    where SR: CarrierError,
+          B: HasDual,
+          P: SpliceUntil<SR, B, N>,
+          T: ChannelRecv<Crr = SR> + ChannelSend<Crr = SR>,
+          SR::Error: From<<T as ChannelRecv>::Err> + From<<T as ChannelSend>::Err>
+{
+    fn splice<EA, EB>(near: Chan<SR, EA, Recv<T, P>>, far: Chan<SR, EB, Send<T, P::Dual>>) ->
+        Result<(Chan<SR, EA, B>, Chan<SR, EB, B::Dual>), SR::Error>
+    {
+        let (near, value) = match near.recv() {
+            Ok(step) =>
+                step,
+            Err(e) => {
+                close_chan(far);
+                return Err(e.into());
+            },
+        };
+        match far.send(value) {
+            Ok(far) =>
+                P::splice(near, far),
+            Err(e) => {
+                close_chan(near);
+                Err(e.into())
+            },
+        }
+    }
+}
+
+impl<SR, B, N, T, P> SpliceUntil<SR, B, S<N>> for Send<T, P>
+    where SR: CarrierError,
+          B: HasDual,
+          P: SpliceUntil<SR, B, N>,
+          T: ChannelRecv<Crr = SR> + ChannelSend<Crr = SR>,
+          SR::Error: From<<T as ChannelRecv>::Err> + From<<T as ChannelSend>::Err>
+{
+    fn splice<EA, EB>(near: Chan<SR, EA, Send<T, P>>, far: Chan<SR, EB, Recv<T, P::Dual>>) ->
+        Result<(Chan<SR, EA, B>, Chan<SR, EB, B::Dual>), SR::Error>
+    {
+        let (far, value) = match far.recv() {
+            Ok(step) =>
+                step,
+            Err(e) => {
+                close_chan(near);
+                return Err(e.into());
+            },
+        };
+        match near.send(value) {
+            Ok(near) =>
+                P::splice(near, far),
+            Err(e) => {
+                close_chan(far);
+                Err(e.into())
+            },
+        }
+    }
+}
+
+impl<SR, E, P> Chan<SR, E, P> where SR: CarrierError {
+    /// Relay values between two sessions of a proxy: `self` faces one peer
+    /// following `P`, `far` faces the other one following the dual of `P`.
+    /// Every value received on one side is sent on the other, until both
+    /// channels reach the boundary protocol `B`, which is then handed back
+    /// for custom handling (typically an `Offer` the proxy wants to
+    /// intercept).
+    ///
+    /// The boundary is the protocol remaining at the position where
+    /// relaying should stop, e.g. `Offer<Q, L>` for `P = Recv<T, Offer<Q,
+    /// L>>`. Only `Send` and `Recv` steps are relayed. If `B` occurs more
+    /// than once along the way, specify the number of steps `N` explicitly
+    /// (`Z`, `S<Z>`, ...).
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn splice_until<B, N, EB>(self, far: Chan<SR, EB, P::Dual>) -> Result<(Chan<SR, E, B>, Chan<SR, EB, B::Dual>), SR::Error>
+        where P: SpliceUntil<SR, B, N>, B: HasDual
+    {
+        P::splice(self, far)
+    }
+}
+
+impl<SR, E, H, T, P> Chan<SR, E, SendWithHeader<H, T, P>> where SR: Carrier, (H, T): ChannelSend<Crr = SR> {
+    /// Send the `header` and the value `v` together, as a single message
+    /// on carriers which transfer tuples in one piece. Returns a channel
+    /// with protocol `P`
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn send_with_header(mut self, header: H, v: T) -> Result<Chan<SR, E, P>, <(H, T) as ChannelSend>::Err> {
+        match (header, v).send(&mut self.carrier) {
+            Ok(()) =>
+                Ok(step_chan(self)),
+            Err(e) => {
+                close_chan(self);
+                Err(e)
+            },
+        }
+    }
+}
+
+impl<SR, E, H, T, P> Chan<SR, E, RecvWithHeader<H, T, P>> where SR: Carrier, (H, T): ChannelRecv<Crr = SR> {
+    /// Receives a header along with a value. Returns a tuple containing
+    /// the resulting channel, the header and the value.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn recv_with_header(mut self) -> Result<(Chan<SR, E, P>, H, T), <(H, T) as ChannelRecv>::Err> {
+        match <(H, T) as ChannelRecv>::recv(&mut self.carrier) {
+            Ok((header, v)) =>
+                Ok((step_chan(self), header, v)),
+            Err(e) => {
+                close_chan(self);
+                Err(e)
+            },
+        }
+    }
+}
+
+impl<SR, E, T, P> Chan<SR, E, SendOpt<T, P>> where SR: Carrier, T: ChannelSend<Crr = SR> {
+    /// Send the value `v`. Returns a channel with protocol `P`
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn send_some(mut self, v: T) -> Result<Chan<SR, E, P>, ChoiceValueError<SR::SendChoiceErr, T::Err>> {
+        let sent = match self.carrier.send_choice(true) {
+            Ok(()) =>
+                v.send(&mut self.carrier).map_err(ChoiceValueError::Value),
+            Err(e) =>
+                Err(ChoiceValueError::Choice(e)),
+        };
+        match sent {
+            Ok(()) =>
+                Ok(step_chan(self)),
+            Err(e) => {
+                close_chan(self);
+                Err(e)
+            },
+        }
+    }
+
+    /// Send nothing. Returns a channel with protocol `P`
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn send_none(mut self) -> Result<Chan<SR, E, P>, SR::SendChoiceErr> {
         match self.carrier.send_choice(false) {
             Ok(()) =>
-                Ok(cast_chan(self)),
+                Ok(step_chan(self)),
             Err(e) => {
                 close_chan(self);
                 Err(e)
@@ -231,54 +1490,252 @@ impl<SR, E, P, Q, L> Chan<SR, E, Choose<P, Choose<Q, L>>> where SR: Carrier {
         }
     }
 
-    /// Perform an active choice, selecting the second element of the choose list.
-    #[must_use]
-    pub fn second(self) -> Result<Chan<SR, E, Q>, SR::SendChoiceErr> {
-        self.cdr().and_then(|c| c.car())
+    /// Send the value if there is one, nothing otherwise.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn send_opt(self, v: Option<T>) -> Result<Chan<SR, E, P>, ChoiceValueError<SR::SendChoiceErr, T::Err>> {
+        match v {
+            Some(v) =>
+                self.send_some(v),
+            None =>
+                self.send_none().map_err(ChoiceValueError::Choice),
+        }
     }
 }
 
-impl<SR, Z, PA, PB, Q, L> Chan<SR, Z, Choose<PA, Choose<PB, Choose<Q, L>>>> where SR: Carrier {
-    /// Convenience function. This is identical to `.cdr().cdr()`
-    #[must_use]
-    pub fn cddr(self) -> Result<Chan<SR, Z, Choose<Q, L>>, SR::SendChoiceErr> {
-        self.cdr().and_then(|c| c.cdr())
+impl<SR, E, T, P> Chan<SR, E, RecvOpt<T, P>> where SR: Carrier, T: ChannelRecv<Crr = SR> {
+    /// Receives a value if the peer has sent one. Returns a tuple
+    /// containing the resulting channel and the value, if any.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn recv_opt(mut self) -> Result<(Chan<SR, E, P>, Option<T>), ChoiceValueError<SR::RecvChoiceErr, T::Err>> {
+        let received = match self.carrier.recv_choice() {
+            Ok(true) =>
+                <T as ChannelRecv>::recv(&mut self.carrier).map(Some).map_err(ChoiceValueError::Value),
+            Ok(false) =>
+                Ok(None),
+            Err(e) =>
+                Err(ChoiceValueError::Choice(e)),
+        };
+        match received {
+            Ok(v) =>
+                Ok((step_chan(self), v)),
+            Err(e) => {
+                close_chan(self);
+                Err(e)
+            },
+        }
     }
+}
+
+/// A channel suspended until both parallel sub-sessions of `Par` are
+/// complete, see `Chan::par`.
+#[must_use = "session channels must reach End and be closed"]
+pub struct ParJoin<SR, E, R>(Chan<SR, E, R>);
 
-    /// Perform an active choice, selecting the third element of the choose list.
-    #[must_use]
-    pub fn third(self) -> Result<Chan<SR, Z, Q>, SR::SendChoiceErr> {
-        self.cddr().and_then(|c| c.car())
+impl<SR, E, P, Q, R> Chan<SR, E, Par<P, Q, R>> where SR: Split {
+    /// Split the channel into two independent channels following protocols
+    /// `P` and `Q` respectively, which could be driven concurrently (for
+    /// example, on different threads). The continuation `R` is available
+    /// via `ParJoin::join` once both of them reach `End`.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn par(mut self) -> Result<(Chan<SR, (), P>, Chan<SR, (), Q>, ParJoin<SR, E, R>), SR::SplitErr> {
+        match self.carrier.split() {
+            Ok((carrier_p, carrier_q)) =>
+                Ok((Chan::new(carrier_p), Chan::new(carrier_q), ParJoin(cast_chan(self)))),
+            Err(e) => {
+                close_chan(self);
+                Err(e)
+            },
+        }
     }
 }
 
-impl<SR, Z, PA, PB, PC, Q, L> Chan<SR, Z, Choose<PA, Choose<PB, Choose<PC, Choose<Q, L>>>>> where SR: Carrier {
-    /// Convenience function. This is identical to `.cdr().cdr().cdr()`
-    #[must_use]
-    pub fn cdddr(self) -> Result<Chan<SR, Z, Choose<Q, L>>, SR::SendChoiceErr> {
-        self.cddr().and_then(|c| c.cdr())
+impl<SR, E, R> ParJoin<SR, E, R> {
+    /// Close both complete parallel sub-sessions and resume the suspended
+    /// channel with protocol `R`.
+    pub fn join<EP, EQ>(self, chan_p: Chan<SR, EP, End>, chan_q: Chan<SR, EQ, End>) -> Chan<SR, E, R> {
+        chan_p.close();
+        chan_q.close();
+        self.0
+    }
+}
+
+impl<SR, E, P, L> Chan<SR, E, Choose<P, L>> where SR: Carrier {
+    /// Perform an active choice, selecting protocol `P` (head of the choose list).
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn car(mut self) -> Result<Chan<SR, E, P>, SR::SendChoiceErr> {
+        match self.carrier.send_choice(true) {
+            Ok(()) =>
+                Ok(step_chan(self)),
+            Err(e) => {
+                close_chan(self);
+                Err(e)
+            },
+        }
     }
 
-    /// Perform an active choice, selecting the fourth element of the choose list.
-    #[must_use]
-    pub fn fourth(self) -> Result<Chan<SR, Z, Q>, SR::SendChoiceErr> {
-        self.cdddr().and_then(|c| c.car())
+    /// alias to `car` method
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn first(self) -> Result<Chan<SR, E, P>, SR::SendChoiceErr> {
+        self.car()
     }
 }
 
-impl<SR, Z, PA, PB, PC, PD, Q, L>
-    Chan<SR, Z, Choose<PA, Choose<PB, Choose<PC, Choose<PD, Choose<Q, L>>>>>> where SR: Carrier
-{
-    /// Convenience function. This is identical to `.cdr().cdr().cdr().cdr()`
-    #[must_use]
-    pub fn cddddr(self) -> Result<Chan<SR, Z, Choose<Q, L>>, SR::SendChoiceErr> {
-        self.cdddr().and_then(|c| c.cdr())
+impl<SR, E, P, L> Chan<SR, E, Choose<P, L>> {
+    /// Narrow the choice down to the first branches of the choose list, so
+    /// that code written against a smaller protocol can drive a peer offering
+    /// more branches. The dropped branches can no longer be selected; the
+    /// remaining ones keep their positions and hence their wire encoding.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn downgrade_choose<M>(self) -> Chan<SR, E, Choose<P, M>> where L: ChoosePrefix<M> {
+        cast_chan(self)
     }
+}
 
-    /// Perform an active choice, selecting the fifth element of the choose list.
-    #[must_use]
-    pub fn fifth(self) -> Result<Chan<SR, Z, Q>, SR::SendChoiceErr> {
-        self.cddddr().and_then(|c| c.car())
+impl<SR, E, P, Q, L> Chan<SR, E, Choose<P, Choose<Q, L>>> where SR: Carrier {
+     /// Perform an active choice, skipping first element and selecting tail of the choose list.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn cdr(mut self) -> Result<Chan<SR, E, Choose<Q, L>>, SR::SendChoiceErr> {
+        match self.carrier.send_choice(false) {
+            Ok(()) =>
+                Ok(step_chan(self)),
+            Err(e) => {
+                close_chan(self);
+                Err(e)
+            },
+        }
+    }
+}
+
+/// The choose list of the branches `P...` followed by `Choose<Q, L>`.
+macro_rules! choose_list {
+    (; $Q:ident $L:ident) => { Choose<$Q, $L> };
+    ($P:ident $($rest:ident)*; $Q:ident $L:ident) => { Choose<$P, choose_list!($($rest)*; $Q $L)> };
+}
+
+/// Choosing the branch `Q` right after the branches `P...`, as a single
+/// run of choices. With a `$skip` name, also skipping to the choose list
+/// starting at `Q`.
+macro_rules! choose_nth {
+    (@false $P:ident) => { false };
+    ($nth:ident $ordinal:expr; $($P:ident)+) => {
+        impl<SR, E, $($P,)+ Q, L> Chan<SR, E, choose_list!($($P)+; Q L)> where SR: Carrier {
+            #[doc = concat!("Perform an active choice, selecting the ", $ordinal, " element of the choose list.")]
+            #[must_use = "session channels must reach End and be closed"]
+            pub fn $nth(self) -> Result<Chan<SR, E, Q>, SR::SendChoiceErr> {
+                choose_run(self, &[$(choose_nth!(@false $P),)+ true])
+            }
+        }
+    };
+    ($nth:ident $ordinal:expr, $skip:ident $cdrs:expr; $($P:ident)+) => {
+        choose_nth!($nth $ordinal; $($P)+);
+
+        impl<SR, E, $($P,)+ Q, L> Chan<SR, E, choose_list!($($P)+; Q L)> where SR: Carrier {
+            #[doc = concat!("Convenience function. This is identical to `", $cdrs, "`, but the")]
+            /// choices are sent as a single run.
+            #[must_use = "session channels must reach End and be closed"]
+            pub fn $skip(self) -> Result<Chan<SR, E, Choose<Q, L>>, SR::SendChoiceErr> {
+                choose_run(self, &[$(choose_nth!(@false $P),)+])
+            }
+        }
+    };
+}
+
+choose_nth!(second "second"; PA);
+choose_nth!(third "third", cddr ".cdr().cdr()"; PA PB);
+choose_nth!(fourth "fourth", cdddr ".cdr().cdr().cdr()"; PA PB PC);
+choose_nth!(fifth "fifth", cddddr ".cdr().cdr().cdr().cdr()"; PA PB PC PD);
+choose_nth!(sixth "sixth"; PA PB PC PD PE);
+choose_nth!(seventh "seventh"; PA PB PC PD PE PF);
+choose_nth!(eighth "eighth"; PA PB PC PD PE PF PG);
+choose_nth!(ninth "ninth"; PA PB PC PD PE PF PG PH);
+choose_nth!(tenth "tenth"; PA PB PC PD PE PF PG PH PI);
+choose_nth!(eleventh "eleventh"; PA PB PC PD PE PF PG PH PI PJ);
+choose_nth!(twelfth "twelfth"; PA PB PC PD PE PF PG PH PI PJ PK);
+choose_nth!(thirteenth "thirteenth"; PA PB PC PD PE PF PG PH PI PJ PK PL);
+choose_nth!(fourteenth "fourteenth"; PA PB PC PD PE PF PG PH PI PJ PK PL PM);
+choose_nth!(fifteenth "fifteenth"; PA PB PC PD PE PF PG PH PI PJ PK PL PM PN);
+choose_nth!(sixteenth "sixteenth"; PA PB PC PD PE PF PG PH PI PJ PK PL PM PN PO);
+
+/// Choose lists with a branch at position `N` (`Z` is the first branch,
+/// `S<Z>` the second and so on).
+pub trait ChooseAt<N> {
+    /// The choose list starting at the branch.
+    type Rest;
+    /// The protocol of the branch.
+    type Branch;
+    /// The number of branches before it.
+    const SKIPPED: usize;
+}
+
+impl<P, L> ChooseAt<Z> for Choose<P, L> {
+    type Rest = Choose<P, L>;
+    type Branch = P;
+    const SKIPPED: usize = 0;
+}
+
+impl<P, L, N> ChooseAt<S<N>> for Choose<P, L> where L: ChooseAt<N> {
+    type Rest = L::Rest;
+    type Branch = L::Branch;
+    const SKIPPED: usize = L::SKIPPED + 1;
+}
+
+impl<SR, E, P, L> Chan<SR, E, Choose<P, L>> where SR: Carrier {
+    /// Navigate the choose list partway: skip the branches before position
+    /// `N`, leaving the choice among the rest to whoever gets the returned
+    /// channel, for example a function building a narrower part of a
+    /// request. Same as `N` calls of `cdr`, but the choices are sent as a
+    /// single run.
+    ///
+    /// The named skips stop at `cddddr`: to skip five branches or more,
+    /// as far as the `sixteenth` branch which the ordinal methods reach,
+    /// use `skip::<N>`.
+    ///
+    /// ```
+    /// use session_types_ng::*;
+    ///
+    /// type N5 = S<S<S<S<S<Z>>>>>;
+    /// type Wide = Offer<End, Offer<End, Offer<End, Offer<End, Offer<End, Offer<End, Offer<End, Offer<End,
+    ///     Offer<End, Offer<End, Offer<End, Offer<End, Offer<End, Offer<End, Offer<End, Offer<End, Nil>>>>>>>>>>>>>>>>;
+    ///
+    /// fn server(chan: Chan<mpsc::Channel, (), Wide>) -> usize {
+    ///     let (index, ()) = chan.offer()
+    ///         .option(Chan::close).option(Chan::close).option(Chan::close).option(Chan::close)
+    ///         .option(Chan::close).option(Chan::close).option(Chan::close).option(Chan::close)
+    ///         .option(Chan::close).option(Chan::close).option(Chan::close).option(Chan::close)
+    ///         .option(Chan::close).option(Chan::close).option(Chan::close)
+    ///         .indexed_option(Chan::close)
+    ///         .unwrap();
+    ///     index
+    /// }
+    ///
+    /// let (srv, cli) = mpsc::session_channel::<Wide>();
+    /// cli.sixth().unwrap().close();
+    /// assert_eq!(server(srv), 5);
+    ///
+    /// let (srv, cli) = mpsc::session_channel::<Wide>();
+    /// cli.sixteenth().unwrap().close();
+    /// assert_eq!(server(srv), 15);
+    ///
+    /// let (srv, cli) = mpsc::session_channel::<Wide>();
+    /// cli.skip::<N5>().unwrap().skip::<N5>().unwrap().third().unwrap().close();
+    /// assert_eq!(server(srv), 12);
+    /// ```
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn skip<N>(self) -> Result<Chan<SR, E, <Choose<P, L> as ChooseAt<N>>::Rest>, SR::SendChoiceErr>
+        where Choose<P, L>: ChooseAt<N>
+    {
+        choose_run(self, &vec![false; <Choose<P, L> as ChooseAt<N>>::SKIPPED])
+    }
+
+    /// Perform an active choice, selecting the branch at position `N`,
+    /// e.g. `pick::<S<S<Z>>>()` is the same as `third()`.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn pick<N>(self) -> Result<Chan<SR, E, <Choose<P, L> as ChooseAt<N>>::Branch>, SR::SendChoiceErr>
+        where Choose<P, L>: ChooseAt<N>
+    {
+        let mut choices = vec![false; <Choose<P, L> as ChooseAt<N>>::SKIPPED];
+        choices.push(true);
+        choose_run(self, &choices)
     }
 }
 
@@ -288,56 +1745,185 @@ enum BranchM<SR, E, P, T> where SR: Carrier {
     Error(SR::RecvChoiceErr),
 }
 
-pub struct Offers<SR, E, P, T>(BranchM<SR, E, P, T>) where SR: Carrier;
+/// The second field is the position of the chosen branch once it has been
+/// handled, or of the next branch to try otherwise.
+pub struct Offers<SR, E, P, T>(BranchM<SR, E, P, T>, usize) where SR: Carrier;
 
 impl<SR, E, P, L> Chan<SR, E, Offer<P, L>> where SR: Carrier {
     /// Passive choice. This allows the other end of the channel to navigate
     /// the given list of options.
-    #[must_use]
+    ///
+    /// A carrier error at any point of the dispatch closes the channel,
+    /// skips the remaining handlers and comes out of the terminal `option`,
+    /// so cleanup which is specific to a carrier failure goes in a single
+    /// place, e.g. `Result::inspect_err`, whatever branch was being tried:
+    ///
+    /// ```
+    /// use session_types_ng::*;
+    ///
+    /// type Srv = Offer<End, Offer<End, Nil>>;
+    ///
+    /// fn server(chan: Chan<mpsc::Channel, (), Srv>) -> Result<usize, mpsc::RecvError> {
+    ///     chan.offer()
+    ///         .option(|chan_a| chan_a.close_with(0))
+    ///         .option(|chan_b| chan_b.close_with(1))
+    ///         .inspect_err(|e| println!("connection torn down during dispatch: {}", e))
+    /// }
+    ///
+    /// // A peer which goes away right away
+    /// let (srv, cli) = mpsc::session_channel::<End>();
+    /// cli.close();
+    /// assert_eq!(server(Chan::new(srv.shutdown())), Err(mpsc::RecvError::Disconnected));
+    /// ```
+    #[must_use = "session channels must reach End and be closed"]
     pub fn offer<T>(self) -> Offers<SR, E, Offer<P, L>, T> {
-        Offers(BranchM::Cdr(self))
+        Offers(BranchM::Cdr(self), 0)
+    }
+}
+
+impl<SR, E, P, L> Chan<SR, E, Offer<P, L>> where SR: PollChoice {
+    /// Same as `offer`, but without blocking: if the peer has not made its
+    /// choice yet, the channel is handed back unchanged in `Err`, so that
+    /// an event loop could poll many sessions parked at an offer and only
+    /// dispatch the ready ones. A carrier error is reported by the `Offers`
+    /// builder, like in `offer`.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn try_offer<T>(mut self) -> Result<Offers<SR, E, Offer<P, L>, T>, Chan<SR, E, Offer<P, L>>> {
+        match self.carrier.poll_choice(Duration::ZERO) {
+            Ok(true) =>
+                Ok(self.offer()),
+            Ok(false) =>
+                Err(self),
+            Err(e) => {
+                close_chan(self);
+                Ok(Offers(BranchM::Error(e), 0))
+            },
+        }
+    }
+}
+
+impl<SR, E, P, L> Chan<SR, E, Offer<P, L>> where SR: PollChoice + IdleTimeout {
+    /// Same as `offer`, but gives up on a peer which makes no choice within
+    /// the idle timeout of the carrier (see `set_idle_timeout`): the channel
+    /// is closed and `IdleClosed` is returned, so that a server loop could
+    /// free the session and move on. A peer quitting on its own picks a
+    /// branch of the offer instead, and a failing carrier is reported by the
+    /// `Offers` builder, like in `offer`.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn offer_unless_idle<T>(mut self) -> Result<Offers<SR, E, Offer<P, L>, T>, IdleClosed> {
+        // Without a timeout of its own the poll ends with a choice or an error
+        match self.carrier.poll_choice(Duration::MAX) {
+            Ok(_) =>
+                Ok(self.offer()),
+            Err(ref e) if SR::is_idle_timeout(e) => {
+                close_chan(self);
+                Err(IdleClosed)
+            },
+            Err(e) => {
+                close_chan(self);
+                Ok(Offers(BranchM::Error(e), 0))
+            },
+        }
     }
 }
 
 impl<SR, E, P, Q, L, T> Offers<SR, E, Offer<P, Offer<Q, L>>, T> where SR: Carrier {
-    #[must_use]
+    #[must_use = "session channels must reach End and be closed"]
     pub fn option<F>(self, mut handler: F) -> Offers<SR, E, Offer<Q, L>, T>
         where F: FnMut(Chan<SR, E, P>) -> T
     {
-        match self.0 {
+        let Offers(branch, index) = self;
+        match branch {
             BranchM::Car(value) =>
-                Offers(BranchM::Car(value)),
+                Offers(BranchM::Car(value), index),
             BranchM::Cdr(mut chan) =>
                 match chan.carrier.recv_choice() {
                     Ok(true) =>
-                        Offers(BranchM::Car(handler(cast_chan(chan)))),
+                        Offers(BranchM::Car(handler(step_chan(chan))), index),
                     Ok(false) =>
-                        Offers(BranchM::Cdr(cast_chan(chan))),
+                        Offers(BranchM::Cdr(step_chan(chan)), index + 1),
                     Err(e) => {
                         close_chan(chan);
-                        Offers(BranchM::Error(e))
+                        Offers(BranchM::Error(e), index)
                     },
                 },
             BranchM::Error(err) =>
-                Offers(BranchM::Error(err)),
+                Offers(BranchM::Error(err), index),
         }
     }
 }
 
 impl<SR, E, P, T> Offers<SR, E, Offer<P, Nil>, T> where SR: Carrier {
-    #[must_use]
-    pub fn option<F>(self, mut handler: F) -> Result<T, SR::RecvChoiceErr>
+    /// Handle the last offered protocol and finish the offer. Every branch
+    /// handler produces the same type `T`, so the outcome of whichever
+    /// branch was chosen can be post-processed uniformly with `Result::map`:
+    ///
+    /// ```
+    /// use session_types_ng::*;
+    ///
+    /// type Srv = Offer<Recv<mpsc::Value<i64>, End>, Offer<Recv<mpsc::Value<f64>, End>, Nil>>;
+    ///
+    /// fn server(chan: Chan<mpsc::Channel, (), Srv>) -> Result<String, mpsc::RecvError> {
+    ///     chan.offer()
+    ///         .option(|chan_int| {
+    ///             let (chan, n) = chan_int.recv_value().unwrap();
+    ///             chan.close();
+    ///             n as f64
+    ///         })
+    ///         .option(|chan_float| {
+    ///             let (chan, x) = chan_float.recv_value().unwrap();
+    ///             chan.close();
+    ///             x
+    ///         })
+    ///         .map(|x| format!("{:.2}", x))
+    /// }
+    ///
+    /// let (srv, cli) = mpsc::session_channel();
+    /// cli.second().unwrap().send_value(0.5).unwrap().close();
+    /// assert_eq!(server(srv).unwrap(), "0.50");
+    /// ```
+    ///
+    /// A peer selecting a branch past the end of the list fails the offer
+    /// with the `InvalidChoice` error of the carrier, and the channel is
+    /// closed:
+    ///
+    /// ```
+    /// use session_types_ng::*;
+    ///
+    /// // A peer which has been built against a wider protocol
+    /// let (srv, cli) = mpsc::session_channel::<End>();
+    /// let srv: Chan<_, (), Offer<End, Nil>> = Chan::new(srv.shutdown());
+    /// let cli: Chan<_, (), Choose<End, Choose<End, Nil>>> = Chan::new(cli.shutdown());
+    ///
+    /// cli.second().unwrap().close();
+    /// assert_eq!(srv.offer().option(Chan::close), Err(mpsc::RecvError::InvalidChoice));
+    /// ```
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn option<F>(self, handler: F) -> Result<T, SR::RecvChoiceErr>
         where F: FnMut(Chan<SR, E, P>) -> T
     {
-        match self.0 {
+        self.indexed_option(handler).map(|(_, value)| value)
+    }
+
+    /// Same as `option`, but the result also carries the position of the
+    /// branch the peer has chosen, starting from zero. Handy for logging
+    /// and per-branch metrics.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn indexed_option<F>(self, mut handler: F) -> Result<(usize, T), SR::RecvChoiceErr>
+        where F: FnMut(Chan<SR, E, P>) -> T
+    {
+        let Offers(branch, index) = self;
+        match branch {
             BranchM::Car(value) =>
-                Ok(value),
+                Ok((index, value)),
             BranchM::Cdr(mut chan) =>
                 match chan.carrier.recv_choice() {
                     Ok(true) =>
-                        Ok(handler(cast_chan(chan))),
-                    Ok(false) =>
-                        panic!("session protocol offer list out of range"),
+                        Ok((index, handler(step_chan(chan)))),
+                    Ok(false) => {
+                        close_chan(chan);
+                        Err(InvalidChoice.into())
+                    },
                     Err(e) => {
                         close_chan(chan);
                         Err(e)
@@ -349,18 +1935,438 @@ impl<SR, E, P, T> Offers<SR, E, Offer<P, Nil>, T> where SR: Carrier {
     }
 }
 
-impl<SR, E, P> Chan<SR, E, Rec<P>> {
+impl<SR, E, P, Q, L, T, H> Offers<SR, E, Offer<P, Offer<Q, L>>, Result<T, H>> where SR: Carrier {
+    /// Same as `option`, for handlers which may fail with an error `H`.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn try_option<F>(self, handler: F) -> Offers<SR, E, Offer<Q, L>, Result<T, H>>
+        where F: FnMut(Chan<SR, E, P>) -> Result<T, H>
+    {
+        self.option(handler)
+    }
+}
+
+impl<SR, E, P, T, H> Offers<SR, E, Offer<P, Nil>, Result<T, H>> where SR: Carrier {
+    /// Same as `option`, for handlers which may fail with an error `H`:
+    /// the error of the chosen handler and the carrier error of the offer
+    /// are reported side by side instead of a nested `Result`.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn try_option<F>(self, handler: F) -> Result<T, OfferError<H, SR::RecvChoiceErr>>
+        where F: FnMut(Chan<SR, E, P>) -> Result<T, H>
+    {
+        match self.option(handler) {
+            Ok(Ok(value)) =>
+                Ok(value),
+            Ok(Err(e)) =>
+                Err(OfferError::Handler(e)),
+            Err(e) =>
+                Err(OfferError::Choice(e)),
+        }
+    }
+}
+
+/// A handler of the offered protocol `P`, see `Chan::dispatch_with`.
+pub trait Handle<SR, E, P> {
+    type Output;
+
+    fn handle(&mut self, chan: Chan<SR, E, P>) -> Self::Output;
+}
+
+/// Handler sets which are able to handle every branch of the offer list
+/// `L`, producing a `T`. Implemented for every type which implements
+/// `Handle` for each of the branches.
+pub trait Dispatch<SR, E, L, T> where SR: Carrier {
+    fn dispatch(&mut self, offers: Offers<SR, E, L, T>) -> Result<T, SR::RecvChoiceErr>;
+}
+
+impl<SR, E, P, Q, L, T, H> Dispatch<SR, E, Offer<P, Offer<Q, L>>, T> for H
+    where SR: Carrier, H: Handle<SR, E, P, Output = T> + Dispatch<SR, E, Offer<Q, L>, T>
+{
+    fn dispatch(&mut self, offers: Offers<SR, E, Offer<P, Offer<Q, L>>, T>) -> Result<T, SR::RecvChoiceErr> {
+        let offers = offers.option(|chan| self.handle(chan));
+        self.dispatch(offers)
+    }
+}
+
+impl<SR, E, P, T, H> Dispatch<SR, E, Offer<P, Nil>, T> for H
+    where SR: Carrier, H: Handle<SR, E, P, Output = T>
+{
+    fn dispatch(&mut self, offers: Offers<SR, E, Offer<P, Nil>, T>) -> Result<T, SR::RecvChoiceErr> {
+        offers.option(|chan| self.handle(chan))
+    }
+}
+
+impl<SR, E, P, L> Chan<SR, E, Offer<P, L>> where SR: Carrier {
+    /// Passive choice handled by a reusable handler set, which implements
+    /// `Handle` once per offered protocol. Unlike closures passed to
+    /// `option`, the handler set (and the state it owns) lives across the
+    /// iterations of a recursive server. Branches with identical protocols
+    /// share the same `Handle` implementation.
+    ///
+    /// ```
+    /// use session_types_ng::*;
+    ///
+    /// type Inc = Recv<mpsc::Value<u64>, Var<Z>>;
+    /// type Srv = Offer<End, Offer<Inc, Nil>>;
+    /// type Env = (Srv, ());
+    ///
+    /// struct Counter(u64);
+    ///
+    /// impl Handle<mpsc::Channel, Env, End> for Counter {
+    ///     type Output = Loop<Chan<mpsc::Channel, Env, Srv>, u64>;
+    ///
+    ///     fn handle(&mut self, chan: Chan<mpsc::Channel, Env, End>) -> Self::Output {
+    ///         chan.close();
+    ///         Loop::Break(self.0)
+    ///     }
+    /// }
+    ///
+    /// impl Handle<mpsc::Channel, Env, Inc> for Counter {
+    ///     type Output = Loop<Chan<mpsc::Channel, Env, Srv>, u64>;
+    ///
+    ///     fn handle(&mut self, chan: Chan<mpsc::Channel, Env, Inc>) -> Self::Output {
+    ///         let (chan, n) = chan.recv_value().unwrap();
+    ///         self.0 += n;
+    ///         Loop::Continue(chan.zero())
+    ///     }
+    /// }
+    ///
+    /// let (srv, cli) = mpsc::session_channel::<Rec<Srv>>();
+    /// let cli = cli.enter().second().unwrap().send_value(40).unwrap().zero();
+    /// cli.second().unwrap().send_value(2).unwrap().zero().first().unwrap().close();
+    ///
+    /// let mut counter = Counter(0);
+    /// let total = srv.serve(|chan| chan.dispatch_with(&mut counter).unwrap());
+    /// assert_eq!(total, 42);
+    /// ```
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn dispatch_with<H, T>(self, handlers: &mut H) -> Result<T, SR::RecvChoiceErr>
+        where H: Dispatch<SR, E, Offer<P, L>, T>
+    {
+        handlers.dispatch(self.offer())
+    }
+}
+
+/// Enums with one variant per branch of the offer list `Protocol`, each
+/// holding the continuation channel of its branch. Implemented by the
+/// `offer_enum!` macro.
+pub trait OfferEnum<SR, E>: Sized where SR: Carrier {
+    type Protocol;
+
+    fn from_offer(chan: Chan<SR, E, Self::Protocol>) -> Result<Self, SR::RecvChoiceErr>;
+}
+
+impl<SR, E, P, L> Chan<SR, E, Offer<P, L>> where SR: Carrier {
+    /// Passive choice turned into a value of the enum `O`, so that the
+    /// branches could be handled with an exhaustive `match`.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn offer_enum<O>(self) -> Result<O, SR::RecvChoiceErr> where O: OfferEnum<SR, E, Protocol = Offer<P, L>> {
+        O::from_offer(self)
+    }
+}
+
+/// Define an enum for the branches of an offer list, see `OfferEnum`.
+///
+/// Variants are listed in the order of the branches along with their
+/// protocols; a list which does not match the offer fails to compile.
+/// The enum is generic over the carrier and the environment of the channel.
+///
+/// ```
+/// extern crate session_types_ng;
+///
+/// use session_types_ng::*;
+///
+/// type Srv = Offer<End, Offer<Recv<mpsc::Value<i64>, End>, Nil>>;
+///
+/// offer_enum! {
+///     enum Req for Srv {
+///         Quit(End),
+///         Number(Recv<mpsc::Value<i64>, End>),
+///     }
+/// }
+///
+/// fn main() {
+///     let (srv, cli) = mpsc::session_channel::<Srv>();
+///     cli.second().unwrap().send_value(42).unwrap().close();
+///     match srv.offer_enum().unwrap() {
+///         Req::Quit(chan) =>
+///             chan.close(),
+///         Req::Number(chan) => {
+///             let (chan, n) = chan.recv_value().unwrap();
+///             assert_eq!(n, 42);
+///             chan.close();
+///         },
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! offer_enum {
+    ($(#[$attr:meta])* $vis:vis enum $name:ident for $proto:ty { $($variant:ident($branch:ty)),+ $(,)? }) => {
+        $(#[$attr])*
+        $vis enum $name<SR, E> {
+            $($variant($crate::Chan<SR, E, $branch>)),+
+        }
+
+        impl<SR, E> $crate::OfferEnum<SR, E> for $name<SR, E> where SR: $crate::Carrier {
+            type Protocol = $proto;
+
+            fn from_offer(chan: $crate::Chan<SR, E, $proto>) -> Result<Self, SR::RecvChoiceErr> {
+                chan.offer()$(.option($name::$variant))+
+            }
+        }
+    }
+}
+
+#[cfg(feature = "catch_unwind")]
+fn catch_handler_unwind<C, T, F, G>(chan: C, handler: &mut F, recover: &mut G) -> T
+    where F: FnMut(C) -> T, G: FnMut(Box<dyn Any + std::marker::Send>) -> T
+{
+    // The channel is consumed by the handler, so nothing broken by the panic could be observed later
+    catch_unwind(AssertUnwindSafe(|| handler(chan))).unwrap_or_else(recover)
+}
+
+#[cfg(feature = "catch_unwind")]
+impl<SR, E, P, Q, L, T> Offers<SR, E, Offer<P, Offer<Q, L>>, T> where SR: Carrier {
+    /// Same as `option`, but a panic in the `handler` is caught and the
+    /// `recover` function is used to produce the result from the panic
+    /// payload instead. The branch channel is dropped while unwinding, so
+    /// the session is over and the peer observes a disconnected carrier.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn option_catch_unwind<F, G>(self, mut handler: F, mut recover: G) -> Offers<SR, E, Offer<Q, L>, T>
+        where F: FnMut(Chan<SR, E, P>) -> T + UnwindSafe, G: FnMut(Box<dyn Any + std::marker::Send>) -> T
+    {
+        self.option(|chan| catch_handler_unwind(chan, &mut handler, &mut recover))
+    }
+}
+
+#[cfg(feature = "catch_unwind")]
+impl<SR, E, P, T> Offers<SR, E, Offer<P, Nil>, T> where SR: Carrier {
+    /// Same as `option`, but a panic in the `handler` is caught and the
+    /// `recover` function is used to produce the result from the panic
+    /// payload instead. The branch channel is dropped while unwinding, so
+    /// the session is over and the peer observes a disconnected carrier.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn option_catch_unwind<F, G>(self, mut handler: F, mut recover: G) -> Result<T, SR::RecvChoiceErr>
+        where F: FnMut(Chan<SR, E, P>) -> T + UnwindSafe, G: FnMut(Box<dyn Any + std::marker::Send>) -> T
+    {
+        self.option(|chan| catch_handler_unwind(chan, &mut handler, &mut recover))
+    }
+}
+
+/// Branch lists of a `TaggedChoose` which contain a branch tagged with `T`.
+/// `N` is the position of the branch, it is inferred by the compiler.
+pub trait TaggedBranch<T, N> {
+    type Branch;
+}
+
+impl<T, P, L> TaggedBranch<T, Z> for TaggedChoose<T, P, L> {
+    type Branch = P;
+}
+
+impl<T, U, P, L, N> TaggedBranch<T, S<N>> for TaggedChoose<U, P, L> where L: TaggedBranch<T, N> {
+    type Branch = L::Branch;
+}
+
+impl<SR, E, T, P, L> Chan<SR, E, TaggedChoose<T, P, L>> where SR: Tagged {
+    /// Perform an active choice, selecting the branch tagged with `X`
+    /// wherever it is in the choose list.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn select<X, N>(mut self) -> Result<Chan<SR, E, <TaggedChoose<T, P, L> as TaggedBranch<X, N>>::Branch>, SR::SendTagErr>
+        where X: BranchTag, TaggedChoose<T, P, L>: TaggedBranch<X, N>
+    {
+        match self.carrier.send_tag(X::TAG) {
+            Ok(()) =>
+                Ok(step_chan(self)),
+            Err(e) => {
+                close_chan(self);
+                Err(e)
+            },
+        }
+    }
+}
+
+enum TaggedBranchM<SR, E, P, T> where SR: Tagged {
+    Done(T),
+    Pending(Chan<SR, E, P>, u64),
+    Error(SR::RecvTagErr),
+}
+
+pub struct TaggedOffers<SR, E, P, T>(TaggedBranchM<SR, E, P, T>) where SR: Tagged;
+
+impl<SR, E, X, P, L> Chan<SR, E, TaggedOffer<X, P, L>> where SR: Tagged {
+    /// Passive choice matched by tags. The tag is received right away, and
+    /// then the handler of the branch with the same tag is run, regardless
+    /// of the position of the branch in the list.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn offer_tagged<T>(mut self) -> TaggedOffers<SR, E, TaggedOffer<X, P, L>, T> {
+        match self.carrier.recv_tag() {
+            Ok(tag) =>
+                TaggedOffers(TaggedBranchM::Pending(step_chan(self), tag)),
+            Err(e) => {
+                close_chan(self);
+                TaggedOffers(TaggedBranchM::Error(e))
+            },
+        }
+    }
+}
+
+impl<SR, E, X, P, Y, Q, L, T> TaggedOffers<SR, E, TaggedOffer<X, P, TaggedOffer<Y, Q, L>>, T> where SR: Tagged, X: BranchTag {
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn option<F>(self, mut handler: F) -> TaggedOffers<SR, E, TaggedOffer<Y, Q, L>, T>
+        where F: FnMut(Chan<SR, E, P>) -> T
+    {
+        match self.0 {
+            TaggedBranchM::Done(value) =>
+                TaggedOffers(TaggedBranchM::Done(value)),
+            TaggedBranchM::Pending(chan, tag) if tag == X::TAG =>
+                TaggedOffers(TaggedBranchM::Done(handler(step_chan(chan)))),
+            TaggedBranchM::Pending(chan, tag) =>
+                TaggedOffers(TaggedBranchM::Pending(cast_chan(chan), tag)),
+            TaggedBranchM::Error(err) =>
+                TaggedOffers(TaggedBranchM::Error(err)),
+        }
+    }
+}
+
+impl<SR, E, X, P, T> TaggedOffers<SR, E, TaggedOffer<X, P, Nil>, T> where SR: Tagged, X: BranchTag {
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn option<F>(self, mut handler: F) -> Result<T, SR::RecvTagErr>
+        where F: FnMut(Chan<SR, E, P>) -> T
+    {
+        match self.0 {
+            TaggedBranchM::Done(value) =>
+                Ok(value),
+            TaggedBranchM::Pending(chan, tag) if tag == X::TAG =>
+                Ok(handler(step_chan(chan))),
+            TaggedBranchM::Pending(chan, _) => {
+                close_chan(chan);
+                Err(InvalidChoice.into())
+            },
+            TaggedBranchM::Error(err) =>
+                Err(err),
+        }
+    }
+}
+
+impl<SR, E, T, F, POk, PErr> Chan<SR, E, SendResult<T, F, POk, PErr>> where SR: Carrier {
+    /// Choose the `Ok` branch and send the value `v`. Returns a channel
+    /// with protocol `POk`.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn send_ok(self, v: T) -> Result<Chan<SR, E, POk>, ChoiceValueError<SR::SendChoiceErr, T::Err>>
+        where T: ChannelSend<Crr = SR>
+    {
+        self.car()
+            .map_err(ChoiceValueError::Choice)?
+            .send(v)
+            .map_err(ChoiceValueError::Value)
+    }
+
+    /// Choose the `Err` branch and send the value `e`. Returns a channel
+    /// with protocol `PErr`.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn send_err(self, e: F) -> Result<Chan<SR, E, PErr>, ChoiceValueError<SR::SendChoiceErr, F::Err>>
+        where F: ChannelSend<Crr = SR>
+    {
+        self.second()
+            .map_err(ChoiceValueError::Choice)?
+            .send(e)
+            .map_err(ChoiceValueError::Value)
+    }
+}
+
+impl<SR, E, T, F, POk, PErr> Chan<SR, E, RecvResult<T, F, POk, PErr>> where SR: Carrier {
+    /// Receive the branch chosen by the peer together with its value.
+    /// Returns `Ok` with the channel at protocol `POk` and the received `T`,
+    /// or `Err` with the channel at protocol `PErr` and the received `F`.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn recv_result(self) ->
+        Result<Result<(Chan<SR, E, POk>, T), (Chan<SR, E, PErr>, F)>, ChoiceValueError<SR::RecvChoiceErr, T::Err>>
+        where T: ChannelRecv<Crr = SR>, F: ChannelRecv<Crr = SR, Err = T::Err>
+    {
+        self.offer()
+            .option(|chan_ok| chan_ok.recv().map(Ok))
+            .option(|chan_err| chan_err.recv().map(Err))
+            .map_err(ChoiceValueError::Choice)?
+            .map_err(ChoiceValueError::Value)
+    }
+}
+
+impl<SR, E, P> Chan<SR, E, Rec<P>> where P: Productive {
     /// Enter a recursive environment, putting the current environment on the
     /// top of the environment stack.
-    #[must_use]
+    #[must_use = "session channels must reach End and be closed"]
     pub fn enter(self) -> Chan<SR, (P, E), P> {
         cast_chan(self)
     }
 }
 
+impl<SR, E, P> Chan<SR, E, Rec<P>> where P: Productive {
+    /// Enter a recursive environment and run `step` for every iteration of
+    /// the recursion. The `step` receives the channel at the beginning of
+    /// the recursive protocol `P` and either returns it back recursed with
+    /// `zero` to continue, or finishes the session and breaks the loop with
+    /// a result.
+    pub fn serve<F, T>(self, mut step: F) -> T
+        where F: FnMut(Chan<SR, (P, E), P>) -> Loop<Chan<SR, (P, E), P>, T>
+    {
+        let mut chan = self.enter();
+        loop {
+            match step(chan) {
+                Loop::Continue(next_chan) =>
+                    chan = next_chan,
+                Loop::Break(value) =>
+                    return value,
+            }
+        }
+    }
+}
+
+/// The outcome of `Chan::serve_with_ticks`.
+pub enum Ticked<C, T> {
+    /// The session has been finished with a result.
+    Finished(T),
+    /// The tick function has stopped the loop while waiting for a request,
+    /// the channel `C` is ready for the next offer.
+    Stopped(C),
+}
+
+impl<SR, E, Q, L> Chan<SR, E, Rec<Offer<Q, L>>> where SR: PollChoice {
+    /// Same as `serve`, for servers which need housekeeping (heartbeats,
+    /// idle timeouts) between requests: whenever no request arrives within
+    /// `interval`, `tick` is run, and the loop keeps waiting if it returns
+    /// `true` or stops otherwise. A request which arrives while a tick runs
+    /// is not lost, it is handled by the next `step`.
+    pub fn serve_with_ticks<F, G, T>(self, interval: Duration, mut tick: G, mut step: F) ->
+        Result<Ticked<Chan<SR, (Offer<Q, L>, E), Offer<Q, L>>, T>, SR::RecvChoiceErr>
+        where F: FnMut(Chan<SR, (Offer<Q, L>, E), Offer<Q, L>>) -> Loop<Chan<SR, (Offer<Q, L>, E), Offer<Q, L>>, T>,
+              G: FnMut() -> bool
+    {
+        let mut chan = self.enter();
+        loop {
+            match chan.carrier.poll_choice(interval) {
+                Ok(true) =>
+                    (),
+                Ok(false) if tick() =>
+                    continue,
+                Ok(false) =>
+                    return Ok(Ticked::Stopped(chan)),
+                Err(e) => {
+                    close_chan(chan);
+                    return Err(e);
+                },
+            }
+            match step(chan) {
+                Loop::Continue(next_chan) =>
+                    chan = next_chan,
+                Loop::Break(value) =>
+                    return Ok(Ticked::Finished(value)),
+            }
+        }
+    }
+}
+
 impl<SR, E, P> Chan<SR, (P, E), Var<Z>> {
     /// Recurse to the environment on the top of the environment stack.
-    #[must_use]
+    #[must_use = "session channels must reach End and be closed"]
     pub fn zero(self) -> Chan<SR, (P, E), P> {
         cast_chan(self)
     }
@@ -368,8 +2374,88 @@ impl<SR, E, P> Chan<SR, (P, E), Var<Z>> {
 
 impl<SR, E, P, N> Chan<SR, (P, E), Var<S<N>>> {
     /// Pop the top environment from the environment stack.
-    #[must_use]
+    #[must_use = "session channels must reach End and be closed"]
     pub fn succ(self) -> Chan<SR, E, Var<N>> {
         cast_chan(self)
     }
 }
+
+/// Environment stacks with a recursion target at depth `N`: `Z` is the top
+/// of the stack (the innermost enclosing `Rec`), `S<Z>` the one below it,
+/// and so on.
+pub trait EnvAt<N> {
+    /// The environment stack once the recursion target is on its top.
+    type Env;
+    /// The recursion target.
+    type Target;
+}
+
+impl<P, E> EnvAt<Z> for (P, E) {
+    type Env = (P, E);
+    type Target = P;
+}
+
+impl<P, E, N> EnvAt<S<N>> for (P, E) where E: EnvAt<N> {
+    type Env = E::Env;
+    type Target = E::Target;
+}
+
+impl<SR, E, N> Chan<SR, E, Var<N>> where E: EnvAt<N> {
+    /// Recurse to the enclosing `Rec` the variable refers to, whatever the
+    /// depth: `Var<Z>` continues the innermost loop like `zero`, `Var<S<Z>>`
+    /// breaks out to the loop around it like `succ().zero()`, and so on.
+    ///
+    /// ```
+    /// use session_types_ng::*;
+    /// use session_types_ng::mpsc::Value;
+    ///
+    /// // An outer loop of batches, each one an inner loop of items.
+    /// type Inner = Offer<Recv<Value<u32>, Var<Z>>, Offer<Var<S<Z>>, Nil>>;
+    /// type Outer = Offer<Rec<Inner>, Offer<End, Nil>>;
+    ///
+    /// fn server(chan: Chan<mpsc::Channel, (), Rec<Outer>>) -> Vec<u32> {
+    ///     let mut sums = Vec::new();
+    ///     chan.serve(|chan_outer| {
+    ///         chan_outer.offer()
+    ///             .option(|chan_batch| {
+    ///                 let mut sum = 0;
+    ///                 let chan_outer = chan_batch.serve(|chan_inner| {
+    ///                     chan_inner.offer()
+    ///                         .option(|chan_item| {
+    ///                             let (chan, n) = chan_item.recv_value().unwrap();
+    ///                             sum += n;
+    ///                             Loop::Continue(chan.recurse())
+    ///                         })
+    ///                         .option(|chan_done| Loop::Break(chan_done.recurse()))
+    ///                         .unwrap()
+    ///                 });
+    ///                 sums.push(sum);
+    ///                 Loop::Continue(chan_outer)
+    ///             })
+    ///             .option(|chan_stop| {
+    ///                 chan_stop.close();
+    ///                 Loop::Break(())
+    ///             })
+    ///             .unwrap()
+    ///     });
+    ///     sums
+    /// }
+    ///
+    /// let (srv, cli) = mpsc::session_channel();
+    /// let thread = std::thread::spawn(move || server(srv));
+    /// let mut chan = cli.enter();
+    /// for batch in &[vec![1, 2, 3], vec![], vec![10]] {
+    ///     let mut chan_inner = chan.first().unwrap().enter();
+    ///     for &n in batch {
+    ///         chan_inner = chan_inner.first().unwrap().send_value(n).unwrap().recurse();
+    ///     }
+    ///     chan = chan_inner.second().unwrap().recurse();
+    /// }
+    /// chan.second().unwrap().close();
+    /// assert_eq!(thread.join().unwrap(), vec![6, 0, 10]);
+    /// ```
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn recurse(self) -> Chan<SR, E::Env, E::Target> {
+        cast_chan(self)
+    }
+}
@@ -0,0 +1,263 @@
+//! A list of values streamed one by one using a recursive protocol.
+
+use std::ops::Deref;
+
+use super::{ChannelRecv, Carrier, ChoiceValueError, Chan, cast_chan, close_chan, Rec, Choose, Offer, Send, Recv, End, Var, Z, Nil};
+
+/// Send a list of `A` values: either finish the list or send the next value and recurse.
+pub type SendList<A> = Rec<SendListInner<A>>;
+pub type SendListInner<A> = Choose<End, Choose<Send<A, Var<Z>>, Nil>>;
+
+/// Receive a list of `A` values: the dual of `SendList<A>`.
+pub type RecvList<A> = Rec<RecvListInner<A>>;
+pub type RecvListInner<A> = Offer<End, Offer<Recv<A, Var<Z>>, Nil>>;
+
+/// Send the number `N` of the values first, then the list of `A` values.
+pub type SendCounted<N, A> = Send<N, SendList<A>>;
+
+/// Receive a list of `A` values preceded by their number: the dual of
+/// `SendCounted<N, A>`.
+pub type RecvCounted<N, A> = Recv<N, RecvList<A>>;
+
+/// An iterator over the values received with `RecvList` protocol.
+///
+/// The channel is closed as soon as the peer finishes the list or an error
+/// occurs. Dropping the stream before that, for example after `take` or an
+/// early `break`, abandons the session without waiting for the rest of the
+/// list: the carrier is dropped and the peer fails on its next step. Use
+/// `drain` to follow the protocol to the end instead.
+///
+/// ```
+/// use std::thread::spawn;
+/// use session_types_ng::*;
+/// use session_types_ng::mpsc::Value;
+///
+/// fn send_all(chan: Chan<mpsc::Channel, (), list::SendList<Value<u32>>>) -> Result<(), mpsc::Error> {
+///     let mut chan = chan.enter();
+///     for x in 0 .. 100 {
+///         chan = chan.second()?.send_value(x)?.zero();
+///     }
+///     chan.first()?.close();
+///     Ok(())
+/// }
+///
+/// let (srv, cli) = mpsc::session_channel::<list::RecvList<Value<u32>>>();
+/// let sender = spawn(move || send_all(cli));
+/// let head: Vec<u32> = srv.recv_stream().map(|v| v.unwrap().into_inner()).take(3).collect();
+/// assert_eq!(head, vec![0, 1, 2]);
+/// // The sender has either sent the whole list already or failed midway
+/// let _ = sender.join().unwrap();
+/// ```
+pub struct RecvStream<SR, E, A> {
+    chan: Option<Chan<SR, (RecvListInner<A>, E), RecvListInner<A>>>,
+}
+
+impl<SR, E, A> Drop for RecvStream<SR, E, A> {
+    fn drop(&mut self) {
+        if let Some(chan) = self.chan.take() {
+            close_chan(chan);
+        }
+    }
+}
+
+/// The outcome of `RecvStream::offer_take`.
+pub enum Taken<C, A> {
+    /// The peer has finished the list before the limit was reached, the
+    /// channel is closed.
+    Finished(Vec<A>),
+    /// The limit has been reached, the stream `C` is ready to receive the
+    /// rest of the list.
+    Limited(Vec<A>, C),
+}
+
+impl<SR, E, A> Chan<SR, E, RecvList<A>> {
+    /// Enter the list protocol and turn the channel into an iterator over
+    /// the received values.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn recv_stream(self) -> RecvStream<SR, E, A> {
+        RecvStream {
+            chan: Some(self.enter()),
+        }
+    }
+}
+
+impl<SR, E, A> Chan<SR, E, RecvList<A>> where SR: Carrier, A: ChannelRecv<Crr = SR> {
+    /// Receive and discard the whole list, following the protocol until the
+    /// peer finishes it, so that both sides reach `End` cleanly.
+    ///
+    /// ```
+    /// use std::thread::spawn;
+    /// use session_types_ng::*;
+    /// use session_types_ng::mpsc::Value;
+    ///
+    /// let (srv, cli) = mpsc::session_channel::<list::RecvList<Value<u32>>>();
+    /// let sender = spawn(move || {
+    ///     let mut chan = cli.enter();
+    ///     for x in 0 .. 5 {
+    ///         chan = chan.second().unwrap().send_value(x).unwrap().zero();
+    ///     }
+    ///     chan.first().unwrap().close();
+    /// });
+    /// srv.drain().unwrap().close();
+    /// sender.join().unwrap();
+    /// ```
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn drain(self) -> Result<Chan<SR, E, End>, ChoiceValueError<SR::RecvChoiceErr, A::Err>> {
+        drain_inner(self.enter())
+    }
+}
+
+/// The most values `recv_counted` allocates room for up front, whatever
+/// number the peer announces.
+pub const MAX_PREALLOC: usize = 4096;
+
+impl<SR, E, N, A> Chan<SR, E, RecvCounted<N, A>>
+    where SR: Carrier, N: ChannelRecv<Crr = SR, Err = A::Err> + Deref<Target = usize>, A: ChannelRecv<Crr = SR>
+{
+    /// Receive the number of the values, then the whole list into a vector
+    /// allocated for that many values up front, and close the channel.
+    ///
+    /// The list is still finished by the peer, so a wrong number costs
+    /// reallocations or spare capacity only. The number is not trusted
+    /// further than `MAX_PREALLOC` values: past that, the vector grows as
+    /// the values arrive.
+    ///
+    /// ```
+    /// use session_types_ng::*;
+    /// use session_types_ng::mpsc::Value;
+    ///
+    /// let (srv, cli) = mpsc::session_channel::<list::RecvCounted<Value<usize>, Value<u8>>>();
+    /// let mut chan = cli.send_value(3).unwrap().enter();
+    /// for x in 0 .. 3 {
+    ///     chan = chan.second().unwrap().send_value(x).unwrap().zero();
+    /// }
+    /// chan.first().unwrap().close();
+    ///
+    /// let values = srv.recv_counted().unwrap();
+    /// assert_eq!(values.len(), 3);
+    /// assert_eq!(values.capacity(), 3);
+    ///
+    /// // An absurd number does not make the receiver allocate for it
+    /// let (srv, cli) = mpsc::session_channel::<list::RecvCounted<Value<usize>, Value<u8>>>();
+    /// cli.send_value(usize::MAX).unwrap().enter().first().unwrap().close();
+    /// assert!(srv.recv_counted().unwrap().is_empty());
+    /// ```
+    pub fn recv_counted(self) -> Result<Vec<A>, ChoiceValueError<SR::RecvChoiceErr, A::Err>> {
+        let (chan, count) = self.recv().map_err(ChoiceValueError::Value)?;
+        let mut values = Vec::with_capacity((*count).min(MAX_PREALLOC));
+        for value in chan.recv_stream() {
+            values.push(value?);
+        }
+        Ok(values)
+    }
+}
+
+fn drain_inner<SR, E, A>(mut chan: Chan<SR, (RecvListInner<A>, E), RecvListInner<A>>) ->
+    Result<Chan<SR, E, End>, ChoiceValueError<SR::RecvChoiceErr, A::Err>>
+    where SR: Carrier, A: ChannelRecv<Crr = SR>
+{
+    loop {
+        let step = chan
+            .offer()
+            .option(|chan_stop| Ok(cast_chan(chan_stop)))
+            .option(|chan_value| Err(chan_value.recv()));
+
+        match step {
+            Ok(Ok(chan_end)) =>
+                return Ok(chan_end),
+            Ok(Err(Ok((next_chan, _)))) =>
+                chan = next_chan.zero(),
+            Ok(Err(Err(e))) =>
+                return Err(ChoiceValueError::Value(e)),
+            Err(e) =>
+                return Err(ChoiceValueError::Choice(e)),
+        }
+    }
+}
+
+impl<SR, E, A> Iterator for RecvStream<SR, E, A> where SR: Carrier, A: ChannelRecv<Crr = SR> {
+    type Item = Result<A, ChoiceValueError<SR::RecvChoiceErr, A::Err>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let chan = self.chan.take()?;
+        let step = chan
+            .offer()
+            .option(|chan_stop| {
+                chan_stop.close();
+                None
+            })
+            .option(|chan_value| Some(chan_value.recv()));
+
+        match step {
+            Ok(None) =>
+                None,
+            Ok(Some(Ok((chan, value)))) => {
+                self.chan = Some(chan.zero());
+                Some(Ok(value))
+            },
+            Ok(Some(Err(e))) =>
+                Some(Err(ChoiceValueError::Value(e))),
+            Err(e) =>
+                Some(Err(ChoiceValueError::Choice(e))),
+        }
+    }
+}
+
+impl<SR, E, A> RecvStream<SR, E, A> where SR: Carrier, A: ChannelRecv<Crr = SR> {
+    /// Receive at most `n` values. Returns `Taken::Finished` if the peer has
+    /// finished the list in the meantime, or `Taken::Limited` along with the
+    /// stream otherwise (even if the peer is about to finish the list right
+    /// after the `n`-th value).
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn offer_take(mut self, n: usize) -> Result<Taken<RecvStream<SR, E, A>, A>, ChoiceValueError<SR::RecvChoiceErr, A::Err>> {
+        let mut values = Vec::new();
+        while values.len() < n {
+            match self.next() {
+                None =>
+                    return Ok(Taken::Finished(values)),
+                Some(Ok(value)) =>
+                    values.push(value),
+                Some(Err(e)) =>
+                    return Err(e),
+            }
+        }
+        Ok(Taken::Limited(values, self))
+    }
+
+    /// Stop consuming the stream early: receive and discard the rest of the
+    /// list and close the channel, so that the peer is not left blocked on
+    /// sending into an abandoned session.
+    ///
+    /// ```
+    /// use std::thread::spawn;
+    /// use session_types_ng::*;
+    /// use session_types_ng::list::Taken;
+    /// use session_types_ng::mpsc::Value;
+    ///
+    /// let (srv, cli) = mpsc::session_channel::<list::RecvList<Value<u32>>>();
+    /// let sender = spawn(move || {
+    ///     let mut chan = cli.enter();
+    ///     for x in 0 .. 5 {
+    ///         chan = chan.second().unwrap().send_value(x).unwrap().zero();
+    ///     }
+    ///     chan.first().unwrap().close();
+    /// });
+    /// match srv.recv_stream().offer_take(2).unwrap() {
+    ///     Taken::Limited(values, stream) => {
+    ///         assert_eq!(values, vec![Value(0), Value(1)]);
+    ///         stream.drain().unwrap();
+    ///     },
+    ///     Taken::Finished(_) =>
+    ///         unreachable!(),
+    /// }
+    /// sender.join().unwrap();
+    /// ```
+    pub fn drain(mut self) -> Result<(), ChoiceValueError<SR::RecvChoiceErr, A::Err>> {
+        match self.chan.take() {
+            None =>
+                Ok(()),
+            Some(chan) =>
+                drain_inner(chan).map(|chan| chan.close()),
+        }
+    }
+}
@@ -1,25 +1,310 @@
+use std::{fmt, error};
 use std::thread::spawn;
 use std::mem::transmute;
-use std::sync::mpsc::{Sender, SendError, Receiver, RecvError, channel};
-use super::{ChannelSend, ChannelRecv, Carrier, HasDual, Chan};
+use std::ops::{Deref, DerefMut};
+use std::convert::Infallible;
+use std::time::{Duration, Instant};
+use std::sync::mpsc::{Sender, SendError, Receiver, RecvTimeoutError, channel};
+use super::profile::Profile;
+use super::{ChannelSend, ChannelSendUnsent, ChannelRecv, ChannelSendBatch, ChannelRecvBatch, Carrier, CarrierError, PollChoice, ChoiceValueError, OfferError, Flush, AutoFlush, Deadline, DefaultTimeout, IdleTimeout, SendPressure, Split, Tagged, PeerIdentity, PeerContext, InvalidChoice, HasDual, DualOf, Chan, session_channel_with, cast_chan};
 
 pub struct Channel {
     tx: Sender<Box<u8>>,
     rx: Receiver<Box<u8>>,
+    deadline: Option<Instant>,
+    timeout: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    last_active: Instant,
+    pending_choices: Choices,
+    profile: Option<Box<Profile>>,
+    step_started: Option<Instant>,
+    peer: Option<PeerContext>,
 }
 
-#[derive(Clone, Debug)]
+/// An error returned from a blocking receive on `Channel`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RecvError {
+    /// The opposite endpoint has been dropped.
+    Disconnected,
+    /// The deadline set for the channel has passed.
+    DeadlineExceeded,
+    /// The default timeout set for the channel has elapsed.
+    TimedOut,
+    /// The channel has been idle for longer than its idle timeout.
+    IdleTimeout,
+    /// The opposite endpoint has selected an offer which does not exist.
+    InvalidChoice,
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RecvError::Disconnected =>
+                write!(f, "receiving on a closed channel"),
+            RecvError::DeadlineExceeded =>
+                write!(f, "channel deadline exceeded"),
+            RecvError::TimedOut =>
+                write!(f, "channel operation timed out"),
+            RecvError::IdleTimeout =>
+                write!(f, "channel idle for too long"),
+            RecvError::InvalidChoice =>
+                write!(f, "session protocol offer list out of range"),
+        }
+    }
+}
+
+impl From<InvalidChoice> for RecvError {
+    fn from(_: InvalidChoice) -> RecvError {
+        RecvError::InvalidChoice
+    }
+}
+
+impl error::Error for RecvError {}
+
+/// Any error of a session step over `Channel`: every send, receive,
+/// choice and flush error converts into it, so `?` works across a whole
+/// protocol.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// Sending has failed as the opposite endpoint has been dropped.
+    Disconnected,
+    /// Receiving has failed.
+    Recv(RecvError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Disconnected =>
+                write!(f, "sending on a closed channel"),
+            Error::Recv(ref e) =>
+                e.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl<T> From<SendError<T>> for Error {
+    fn from(_: SendError<T>) -> Error {
+        Error::Disconnected
+    }
+}
+
+impl From<RecvError> for Error {
+    fn from(e: RecvError) -> Error {
+        Error::Recv(e)
+    }
+}
+
+impl From<Infallible> for Error {
+    fn from(e: Infallible) -> Error {
+        match e {}
+    }
+}
+
+impl<H, C> From<OfferError<H, C>> for Error where Error: From<H> + From<C> {
+    fn from(e: OfferError<H, C>) -> Error {
+        match e {
+            OfferError::Handler(e) =>
+                e.into(),
+            OfferError::Choice(e) =>
+                e.into(),
+        }
+    }
+}
+
+impl<C, V> From<ChoiceValueError<C, V>> for Error where Error: From<C> + From<V> {
+    fn from(e: ChoiceValueError<C, V>) -> Error {
+        match e {
+            ChoiceValueError::Choice(e) =>
+                e.into(),
+            ChoiceValueError::Value(e) =>
+                e.into(),
+        }
+    }
+}
+
+/// The slave function of `connect_timeout` has not finished in time.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct JoinTimeout;
+
+impl fmt::Display for JoinTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "timed out waiting for the slave thread")
+    }
+}
+
+impl error::Error for JoinTimeout {}
+
+/// The errors of the functions connected by `connect_try`: at least one of
+/// them has failed.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ConnectError<EM, ES> {
+    pub master: Option<EM>,
+    pub slave: Option<ES>,
+}
+
+impl<EM, ES> fmt::Display for ConnectError<EM, ES> where EM: fmt::Display, ES: fmt::Display {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.master, &self.slave) {
+            (Some(master), Some(slave)) =>
+                write!(f, "master failed: {}; slave failed: {}", master, slave),
+            (Some(master), None) =>
+                write!(f, "master failed: {}", master),
+            (None, Some(slave)) =>
+                write!(f, "slave failed: {}", slave),
+            (None, None) =>
+                write!(f, "no failure"),
+        }
+    }
+}
+
+impl<EM, ES> error::Error for ConnectError<EM, ES> where EM: error::Error, ES: error::Error {}
+
+impl Channel {
+    fn new(tx: Sender<Box<u8>>, rx: Receiver<Box<u8>>) -> Channel {
+        Channel {
+            tx,
+            rx,
+            deadline: None,
+            timeout: None,
+            idle_timeout: None,
+            last_active: Instant::now(),
+            pending_choices: Choices::default(),
+            profile: None,
+            step_started: None,
+            peer: None,
+        }
+    }
+
+    /// A sub-channel of `Split`, which inherits the limits and the peer
+    /// context of the parent.
+    fn sub_channel(&self, tx: Sender<Box<u8>>, rx: Receiver<Box<u8>>) -> Channel {
+        Channel {
+            deadline: self.deadline,
+            timeout: self.timeout,
+            idle_timeout: self.idle_timeout,
+            peer: self.peer.clone(),
+            ..Channel::new(tx, rx)
+        }
+    }
+
+    /// Start timing the current step, unless it is already being timed
+    /// (a step may take several transfers).
+    fn start_step(&mut self) {
+        if self.profile.is_some() && self.step_started.is_none() {
+            self.step_started = Some(Instant::now());
+        }
+    }
+
+    fn recv_boxed<T>(&mut self) -> Result<Box<T>, RecvError> where T: Send + 'static {
+        self.start_step();
+        let rx: &Receiver<Box<T>> = unsafe { transmute(&self.rx) };
+        // Wait for whichever expires first: the default timeout, the idle timeout or the deadline
+        let mut bound = self.timeout.map(|timeout| (timeout, RecvError::TimedOut));
+        // Only read the clock when there is a limit to check it against
+        if self.idle_timeout.is_some() || self.deadline.is_some() {
+            let idle_limit = self.idle_timeout.map(|idle_timeout| self.last_active + idle_timeout);
+            let now = Instant::now();
+            for &(limit, expired) in &[(idle_limit, RecvError::IdleTimeout), (self.deadline, RecvError::DeadlineExceeded)] {
+                if let Some(limit) = limit {
+                    if now >= limit {
+                        return Err(expired);
+                    }
+                    let left = limit - now;
+                    if bound.map_or(true, |(wait, _)| left <= wait) {
+                        bound = Some((left, expired));
+                    }
+                }
+            }
+        }
+        let received = match bound {
+            None =>
+                rx.recv().map_err(|_| RecvError::Disconnected),
+            Some((wait, expired)) =>
+                rx.recv_timeout(wait).map_err(|e| match e {
+                    RecvTimeoutError::Timeout =>
+                        expired,
+                    RecvTimeoutError::Disconnected =>
+                        RecvError::Disconnected,
+                }),
+        };
+        if received.is_ok() {
+            self.touch();
+        }
+        received
+    }
+
+    /// Record activity for the idle timeout. Without an idle timeout
+    /// nothing is recorded, `set_idle_timeout` starts the count afresh.
+    fn touch(&mut self) {
+        if self.idle_timeout.is_some() {
+            self.last_active = Instant::now();
+        }
+    }
+}
+
+/// A value transferred over `Channel`.
+///
+/// `ChannelSend` and `ChannelRecv` tie every value type to a single carrier,
+/// so a blanket implementation for bare types would bind all of them to
+/// `Channel` for good. `Value` is what binds a payload to this carrier, and
+/// protocol types have to name it, e.g. `Send<Value<i64>, End>`. At the use
+/// site the wrapper can be skipped with `Chan::send_value` and
+/// `Chan::recv_value`.
+///
+/// Every value travels boxed, but boxing a zero-sized type does not
+/// allocate, so synchronization beats like `Send<Value<()>, P>` cost no more
+/// than the queue operation itself.
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Value<T>(pub T) where T: Send + 'static;
 
+impl<T> Value<T> where T: Send + 'static {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Value<T> where T: Send + 'static {
+    fn from(value: T) -> Value<T> {
+        Value(value)
+    }
+}
+
+impl<T> Deref for Value<T> where T: Send + 'static {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Value<T> where T: Send + 'static {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
 impl<T> ChannelSend for Value<T> where T: Send + 'static {
     type Crr = Channel;
     type Err = SendError<Box<T>>;
 
     fn send(self, carrier: &mut Self::Crr) -> Result<(), Self::Err> {
+        carrier.start_step();
         unsafe {
             let tx: &Sender<Box<T>> = transmute(&carrier.tx);
-            tx.send(Box::new(self.0))
+            tx.send(Box::new(self.0))?;
         }
+        carrier.touch();
+        Ok(())
+    }
+}
+
+/// `std::sync::mpsc` hands back the boxed value when the peer is gone.
+impl<T> ChannelSendUnsent for Value<T> where T: Send + 'static {
+    fn into_unsent(SendError(value): Self::Err) -> Self {
+        Value(*value)
     }
 }
 
@@ -28,53 +313,449 @@ impl<T> ChannelRecv for Value<T> where T: Sized + Send + 'static {
     type Err = RecvError;
 
     fn recv(carrier: &mut Self::Crr) -> Result<Self, Self::Err> {
-        unsafe {
-            let rx: &Receiver<Box<T>> = transmute(&carrier.rx);
-            rx.recv().map(|v| Value(*v))
+        carrier.recv_boxed().map(|v| Value(*v))
+    }
+}
+
+macro_rules! tuple_value_impls {
+    ($($T:ident $v:ident),+) => {
+        /// A tuple of values is transferred as a single boxed tuple.
+        impl<$($T),+> ChannelSend for ($(Value<$T>,)+) where $($T: Send + 'static),+ {
+            type Crr = Channel;
+            type Err = SendError<Box<($($T,)+)>>;
+
+            fn send(self, carrier: &mut Self::Crr) -> Result<(), Self::Err> {
+                let ($(Value($v),)+) = self;
+                Value(($($v,)+)).send(carrier)
+            }
+        }
+
+        impl<$($T),+> ChannelSendUnsent for ($(Value<$T>,)+) where $($T: Send + 'static),+ {
+            fn into_unsent(SendError(values): Self::Err) -> Self {
+                let ($($v,)+) = *values;
+                ($(Value($v),)+)
+            }
+        }
+
+        impl<$($T),+> ChannelRecv for ($(Value<$T>,)+) where $($T: Send + 'static),+ {
+            type Crr = Channel;
+            type Err = RecvError;
+
+            fn recv(carrier: &mut Self::Crr) -> Result<Self, Self::Err> {
+                let Value(($($v,)+)) = Value::recv(carrier)?;
+                Ok(($(Value($v),)+))
+            }
+        }
+    }
+}
+
+tuple_value_impls!(A a, B b);
+tuple_value_impls!(A a, B b, C c);
+tuple_value_impls!(A a, B b, C c, D d);
+
+/// A batch of values is transferred as a single boxed `Vec`.
+impl<T> ChannelSendBatch for Value<T> where T: Clone + Send + 'static {
+    type Crr = Channel;
+    type Err = SendError<Box<Vec<T>>>;
+    type Item = T;
+
+    fn send_batch(items: &[T], carrier: &mut Self::Crr) -> Result<(), Self::Err> {
+        Value(items.to_vec()).send(carrier)
+    }
+}
+
+impl<T> ChannelRecvBatch for Value<T> where T: Send + 'static {
+    type Crr = Channel;
+    type Err = RecvError;
+    type Item = T;
+
+    fn recv_batch(carrier: &mut Self::Crr) -> Result<Vec<T>, Self::Err> {
+        Value::recv(carrier).map(|Value(items)| items)
+    }
+}
+
+/// A run of choices transferred as a single message: up to 64 choices,
+/// the first one in the lowest bit.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Choices {
+    bits: u64,
+    len: u32,
+}
+
+impl Choices {
+    const CAPACITY: usize = 64;
+
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn to_vec(&self) -> Vec<bool> {
+        (0 .. self.len).map(|i| self.bits & (1 << i) != 0).collect()
+    }
+
+    fn from_slice(choices: &[bool]) -> Choices {
+        let bits = choices.iter().rev().fold(0, |bits, &choice| bits << 1 | choice as u64);
+        Choices { bits, len: choices.len() as u32 }
+    }
+
+    fn pop_front(&mut self) -> Option<bool> {
+        if self.len == 0 {
+            return None;
         }
+        let choice = self.bits & 1 != 0;
+        self.bits >>= 1;
+        self.len -= 1;
+        Some(choice)
     }
 }
 
+/// Choices always travel as runs, a single choice is a run of one. The
+/// rest of a received run is kept in the carrier for the following
+/// `recv_choice` calls.
 impl Carrier for Channel {
-    type SendChoiceErr = SendError<Box<bool>>;
+    type SendChoiceErr = SendError<Box<Choices>>;
     fn send_choice(&mut self, choice: bool) -> Result<(), Self::SendChoiceErr> {
-        Value(choice).send(self)
+        self.send_choice_n(&[choice])
+    }
+
+    fn send_choice_n(&mut self, choices: &[bool]) -> Result<(), Self::SendChoiceErr> {
+        for run in choices.chunks(Choices::CAPACITY) {
+            Value(Choices::from_slice(run)).send(self)?;
+        }
+        Ok(())
     }
 
     type RecvChoiceErr = RecvError;
     fn recv_choice(&mut self) -> Result<bool, Self::RecvChoiceErr> {
-        Value::recv(self).map(|Value(value)| value)
+        if self.pending_choices.is_empty() {
+            self.pending_choices = *self.recv_boxed()?;
+        }
+        self.pending_choices.pop_front().ok_or(RecvError::InvalidChoice)
+    }
+
+    /// Choice runs are only split between the offers of one choose list,
+    /// so no choice may be left pending at a value step. The prefixes
+    /// match every value step on purpose: `Send` and `Recv` as well as
+    /// their batch, header and optional forms.
+    fn debug_check_state(&self, protocol: &'static str) {
+        let step = protocol.trim_start_matches("session_types_ng::");
+        if step.starts_with("Send") || step.starts_with("Recv") {
+            debug_assert!(self.pending_choices.is_empty(),
+                          "choice bits left unread before a value step: {}", protocol);
+        }
+    }
+
+    fn step_performed(&mut self, protocol: &'static str) {
+        if let (Some(profile), Some(started)) = (self.profile.as_mut(), self.step_started.take()) {
+            profile.record(protocol, started.elapsed());
+        }
     }
 }
 
+/// A polled choice is kept in the carrier until `recv_choice` takes it.
+impl PollChoice for Channel {
+    fn poll_choice(&mut self, timeout: Duration) -> Result<bool, Self::RecvChoiceErr> {
+        if !self.pending_choices.is_empty() {
+            return Ok(true);
+        }
+        let default_timeout = self.timeout.replace(timeout);
+        let polled = self.recv_boxed::<Choices>();
+        self.timeout = default_timeout;
+        match polled {
+            Ok(choices) => {
+                self.pending_choices = *choices;
+                Ok(true)
+            },
+            Err(RecvError::TimedOut) =>
+                Ok(false),
+            Err(e) =>
+                Err(e),
+        }
+    }
+}
+
+impl Tagged for Channel {
+    type SendTagErr = SendError<Box<u64>>;
+    fn send_tag(&mut self, tag: u64) -> Result<(), Self::SendTagErr> {
+        Value(tag).send(self)
+    }
+
+    type RecvTagErr = RecvError;
+    fn recv_tag(&mut self) -> Result<u64, Self::RecvTagErr> {
+        Value::recv(self).map(|Value(tag)| tag)
+    }
+}
+
+impl<E, P, T> Chan<Channel, E, super::Send<Value<T>, P>> where T: Send + 'static {
+    /// Send a bare value, wrapping it into `Value`.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn send_value(self, v: T) -> Result<Chan<Channel, E, P>, SendError<Box<T>>> {
+        self.send(Value(v))
+    }
+}
+
+impl<E, P, T> Chan<Channel, E, super::Recv<Value<T>, P>> where T: Send + 'static {
+    /// Receive a bare value, unwrapping it from `Value`.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn recv_value(self) -> Result<(Chan<Channel, E, P>, T), RecvError> {
+        self.recv().map(|(chan, Value(v))| (chan, v))
+    }
+}
+
+impl<E, H, T, P> Chan<Channel, E, super::SendWithHeader<Value<H>, Value<T>, P>> where H: Send + 'static, T: Send + 'static {
+    /// Send a bare header and value, wrapping them into `Value`.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn send_value_with_header(self, header: H, v: T) -> Result<Chan<Channel, E, P>, SendError<Box<(H, T)>>> {
+        self.send_with_header(Value(header), Value(v))
+    }
+}
+
+impl<E, H, T, P> Chan<Channel, E, super::RecvWithHeader<Value<H>, Value<T>, P>> where H: Send + 'static, T: Send + 'static {
+    /// Receive a bare header and value, unwrapping them from `Value`.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn recv_value_with_header(self) -> Result<(Chan<Channel, E, P>, H, T), RecvError> {
+        self.recv_with_header().map(|(chan, Value(header), Value(v))| (chan, header, v))
+    }
+}
+
+impl<E, T, P> Chan<Channel, E, super::SendOpt<Value<T>, P>> where T: Send + 'static {
+    /// Send a bare value or nothing, wrapping the value into `Value`.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn send_opt_value(self, v: Option<T>) -> Result<Chan<Channel, E, P>, ChoiceValueError<SendError<Box<Choices>>, SendError<Box<T>>>> {
+        self.send_opt(v.map(Value))
+    }
+}
+
+impl<E, T, P> Chan<Channel, E, super::RecvOpt<Value<T>, P>> where T: Send + 'static {
+    /// Receive a bare value if the peer has sent one, unwrapping it from `Value`.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn recv_opt_value(self) -> Result<(Chan<Channel, E, P>, Option<T>), ChoiceValueError<RecvError, RecvError>> {
+        self.recv_opt().map(|(chan, v)| (chan, v.map(Value::into_inner)))
+    }
+}
+
+impl<E, P, T> Chan<Channel, E, super::Recv<T, P>> where T: ChannelRecv<Crr = Channel> {
+    /// Receive a value waiting at most `timeout` instead of the default
+    /// timeout of the channel (a deadline, if any, still applies), and
+    /// report how long the receive has actually taken.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn recv_timeout(mut self, timeout: Duration) -> Result<(Chan<Channel, E, P>, T, Duration), T::Err> {
+        let default_timeout = self.carrier.timeout.replace(timeout);
+        let started = Instant::now();
+        let (mut chan, value) = self.recv()?;
+        let elapsed = started.elapsed();
+        chan.carrier.timeout = default_timeout;
+        Ok((chan, value, elapsed))
+    }
+}
+
+impl<E, P> Chan<Channel, E, P> {
+    /// Start recording the latency of every following step into a
+    /// `Profile`, labelling the steps by their protocol types. The latency
+    /// of a step spans its transfers, including the wait for the peer.
+    pub fn measure(&mut self) {
+        if self.carrier.profile.is_none() {
+            self.carrier.profile = Some(Box::default());
+        }
+    }
+
+    /// Attach what is known about the peer of this endpoint, for example
+    /// by an acceptor which has checked its credentials before handing the
+    /// endpoint over to the handler. Sub-channels of `par` inherit it.
+    pub fn set_peer_context(&mut self, peer: Option<PeerContext>) {
+        self.carrier.peer = peer;
+    }
+
+    /// Stop recording and hand out the profile recorded so far, if
+    /// `measure` has been called. Take it before the channel is closed.
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.carrier.step_started = None;
+        self.carrier.profile.take().map(|profile| *profile)
+    }
+}
+
+impl CarrierError for Channel {
+    type Error = Error;
+}
+
+/// The deadline bounds receiving only: sending on `std::sync::mpsc` never blocks.
+impl Deadline for Channel {
+    fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+}
+
+/// Like the deadline, the timeout bounds receiving only. When both are set,
+/// whichever expires first fails the receive.
+/// Sending never blocks, but it counts as activity as well.
+impl IdleTimeout for Channel {
+    fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.idle_timeout = timeout;
+        self.last_active = Instant::now();
+    }
+
+    fn is_idle_timeout(err: &RecvError) -> bool {
+        *err == RecvError::IdleTimeout
+    }
+}
+
+impl DefaultTimeout for Channel {
+    fn set_default_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+}
+
+/// `std::sync::mpsc` queues are unbounded and sending never blocks, so
+/// there is never any pressure.
+impl SendPressure for Channel {
+    fn send_pressure(&self) -> f32 {
+        0.0
+    }
+}
+
+/// The in-process channel authenticates nobody by itself, the peer
+/// context is attached with `Chan::set_peer_context`.
+impl PeerIdentity for Channel {
+    fn peer_context(&self) -> Option<&PeerContext> {
+        self.peer.as_ref()
+    }
+}
+
+/// Each endpoint creates two fresh channels and hands their senders over
+/// to the peer, so the sub-carriers do not share anything with the parent.
+impl Split for Channel {
+    type SplitErr = RecvError;
+
+    fn split(&mut self) -> Result<(Channel, Channel), Self::SplitErr> {
+        let (tx_a, rx_a) = channel();
+        let (tx_b, rx_b) = channel();
+        Value((tx_a, tx_b)).send(self).map_err(|_| RecvError::Disconnected)?;
+        let Value((peer_tx_a, peer_tx_b)) = Value::recv(self)?;
+        Ok((self.sub_channel(peer_tx_a, rx_a), self.sub_channel(peer_tx_b, rx_b)))
+    }
+}
+
+/// `std::sync::mpsc` does not buffer anything on the sending side, so flushing is a no-op.
+impl Flush for Channel {
+    type FlushErr = Infallible;
+
+    fn flush(&mut self) -> Result<(), Self::FlushErr> {
+        Ok(())
+    }
+}
+
+/// There is never any pending output to flush, so auto flushing is a no-op.
+impl AutoFlush for Channel {
+    fn set_auto_flush(&mut self, _enabled: bool) {}
+}
+
 /// Returns two session channels
-#[must_use]
+#[must_use = "session channels must reach End and be closed"]
 pub fn session_channel<P: HasDual>() -> (Chan<Channel, (), P>, Chan<Channel, (), P::Dual>) {
-    let (master_tx, slave_rx) = channel();
-    let (slave_tx, master_rx) = channel();
+    session_channel_with(|| {
+        let (master_tx, slave_rx) = channel();
+        let (slave_tx, master_rx) = channel();
 
-    let master_carrier = Channel {
-        tx: master_tx,
-        rx: master_rx,
-    };
-    let slave_carrier = Channel {
-        tx: slave_tx,
-        rx: slave_rx,
-    };
+        let master_carrier = Channel::new(master_tx, master_rx);
+        let slave_carrier = Channel::new(slave_tx, slave_rx);
 
-    (Chan::new(master_carrier),
-     Chan::new(slave_carrier))
+        (master_carrier, slave_carrier)
+    })
 }
 
 /// Connect two functions using a session typed channel.
-pub fn connect<FM, FS, P>(master_fn: FM, slave_fn: FS) where
+pub fn connect<FM, FS, P, Q>(master_fn: FM, slave_fn: FS) where
     FM: Fn(Chan<Channel, (), P>) + Send,
-    FS: Fn(Chan<Channel, (), P::Dual>) + Send + 'static,
+    FS: Fn(Chan<Channel, (), Q>) + Send + 'static,
     P: HasDual + Send + 'static,
-    <P as HasDual>::Dual: HasDual + Send + 'static
+    Q: DualOf<P> + HasDual + Send + 'static
 {
-    let (master, slave) = session_channel();
+    let (master, slave) = session_channel::<P>();
+    // `DualOf` holds only for `Q = P::Dual`
+    let slave: Chan<Channel, (), Q> = cast_chan(slave);
     let thread = spawn(move || slave_fn(slave));
     master_fn(master);
     thread.join().unwrap();
 }
+
+/// Connect two fallible functions like `connect` does, so that they could
+/// propagate errors with `?` instead of panicking. Both functions are run to
+/// completion, and their errors are returned together. A panic of the slave
+/// function is propagated like in `connect`.
+pub fn connect_try<FM, FS, EM, ES, P, Q>(master_fn: FM, slave_fn: FS) -> Result<(), ConnectError<EM, ES>> where
+    FM: FnOnce(Chan<Channel, (), P>) -> Result<(), EM>,
+    FS: FnOnce(Chan<Channel, (), Q>) -> Result<(), ES> + Send + 'static,
+    ES: Send + 'static,
+    P: HasDual + Send + 'static,
+    Q: DualOf<P> + HasDual + Send + 'static
+{
+    let (master, slave) = session_channel::<P>();
+    // `DualOf` holds only for `Q = P::Dual`
+    let slave: Chan<Channel, (), Q> = cast_chan(slave);
+    let thread = spawn(move || slave_fn(slave));
+    let master = master_fn(master).err();
+    let slave = thread.join().unwrap().err();
+    match (master, slave) {
+        (None, None) =>
+            Ok(()),
+        (master, slave) =>
+            Err(ConnectError { master, slave }),
+    }
+}
+
+/// Connect two functions like `connect` does, but wait for the slave
+/// function at most `timeout` after the master function has returned.
+///
+/// On timeout the slave thread is detached and `JoinTimeout` is returned.
+/// The detached thread keeps running and leaks if it never completes, for
+/// example when it is deadlocked on the session.
+pub fn connect_timeout<FM, FS, P, Q>(master_fn: FM, slave_fn: FS, timeout: Duration) -> Result<(), JoinTimeout> where
+    FM: Fn(Chan<Channel, (), P>) + Send,
+    FS: Fn(Chan<Channel, (), Q>) + Send + 'static,
+    P: HasDual + Send + 'static,
+    Q: DualOf<P> + HasDual + Send + 'static
+{
+    let (master, slave) = session_channel::<P>();
+    // `DualOf` holds only for `Q = P::Dual`
+    let slave: Chan<Channel, (), Q> = cast_chan(slave);
+    let (done_tx, done_rx) = channel();
+    let thread = spawn(move || {
+        slave_fn(slave);
+        let _ = done_tx.send(());
+    });
+    master_fn(master);
+    match done_rx.recv_timeout(timeout) {
+        Err(RecvTimeoutError::Timeout) =>
+            Err(JoinTimeout),
+        // Either finished or panicked: `join` propagates the panic like `connect` does
+        Ok(()) | Err(RecvTimeoutError::Disconnected) => {
+            thread.join().unwrap();
+            Ok(())
+        },
+    }
+}
+
+/// Wire two functions with a session typed channel without running them.
+///
+/// Returns the master and the slave tasks, ready to be scheduled on any
+/// executor or thread pool. The tasks communicate with each other, so they
+/// must be run concurrently: running one to completion before starting the
+/// other blocks forever as soon as the first one waits for its peer.
+pub fn connect_bidi<FM, FS, TM, TS, P, Q>(master_fn: FM, slave_fn: FS) ->
+    (impl FnOnce() -> TM + Send, impl FnOnce() -> TS + Send) where
+    FM: FnOnce(Chan<Channel, (), P>) -> TM + Send,
+    FS: FnOnce(Chan<Channel, (), Q>) -> TS + Send,
+    P: HasDual + Send,
+    Q: DualOf<P> + HasDual + Send
+{
+    let (master, slave) = session_channel::<P>();
+    // `DualOf` holds only for `Q = P::Dual`
+    let slave: Chan<Channel, (), Q> = cast_chan(slave);
+    let master_task = move || master_fn(master);
+    let slave_task = move || slave_fn(slave);
+    (master_task, slave_task)
+}
@@ -0,0 +1,222 @@
+//! Offers whose branch handlers produce values of different types.
+//!
+//! `Chan::offer_each` is the counterpart of `Chan::offer` for servers
+//! where every branch has a natural outcome of its own: each handler keeps
+//! its return type, and the whole offer results in a `OneOfN` sum type
+//! with one variant per branch, in the order of the offer list.
+//!
+//! ```
+//! use session_types_ng::*;
+//! use session_types_ng::outcome::OneOf3;
+//!
+//! type Srv = Offer<Recv<mpsc::Value<u64>, End>, Offer<Recv<mpsc::Value<String>, End>, Offer<End, Nil>>>;
+//!
+//! fn server(chan: Chan<mpsc::Channel, (), Srv>) -> OneOf3<u64, String, ()> {
+//!     chan.offer_each()
+//!         .option(|chan_id| {
+//!             let (chan, id) = chan_id.recv_value().unwrap();
+//!             chan.close();
+//!             id
+//!         })
+//!         .option(|chan_name| {
+//!             let (chan, name) = chan_name.recv_value().unwrap();
+//!             chan.close();
+//!             name
+//!         })
+//!         .option(|chan_quit| chan_quit.close())
+//!         .unwrap()
+//! }
+//!
+//! let (srv, cli) = mpsc::session_channel();
+//! cli.second().unwrap().send_value("alice".to_string()).unwrap().close();
+//! match server(srv) {
+//!     OneOf3::First(id) => println!("by id: {}", id),
+//!     OneOf3::Second(name) => assert_eq!(name, "alice"),
+//!     OneOf3::Third(()) => println!("quit"),
+//! }
+//! ```
+
+use std::convert::Infallible;
+
+use super::{Carrier, Chan, Offer, Nil, InvalidChoice, close_chan, step_chan};
+
+/// The outcome of the branches handled so far: either one of the earlier
+/// branches `L`, or the last one `R`. The final outcome is flattened into
+/// a `OneOfN`, so this type only shows up in the builder signatures.
+pub enum Partial<L, R> {
+    Earlier(L),
+    Last(R),
+}
+
+/// Outcomes of every branch of an offer, which can be flattened into a
+/// single sum type. Implemented for offers of up to six branches.
+pub trait Flatten {
+    type Flat;
+
+    fn flatten(self) -> Self::Flat;
+}
+
+enum EachM<SR, E, P, A> where SR: Carrier {
+    Done(A),
+    Pending(Chan<SR, E, P>),
+    Error(SR::RecvChoiceErr),
+}
+
+/// The builder of `Chan::offer_each`: `A` accumulates the outcome types of
+/// the branches handled so far.
+pub struct OffersEach<SR, E, P, A>(EachM<SR, E, P, A>) where SR: Carrier;
+
+impl<SR, E, P, L> Chan<SR, E, Offer<P, L>> where SR: Carrier {
+    /// Same as `offer`, but every branch handler may produce a value of a
+    /// different type, see the `outcome` module.
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn offer_each(self) -> OffersEach<SR, E, Offer<P, L>, Infallible> {
+        OffersEach(EachM::Pending(self))
+    }
+}
+
+impl<SR, E, P, Q, L, A> OffersEach<SR, E, Offer<P, Offer<Q, L>>, A> where SR: Carrier {
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn option<F, T>(self, handler: F) -> OffersEach<SR, E, Offer<Q, L>, Partial<A, T>>
+        where F: FnOnce(Chan<SR, E, P>) -> T
+    {
+        match self.0 {
+            EachM::Done(outcome) =>
+                OffersEach(EachM::Done(Partial::Earlier(outcome))),
+            EachM::Pending(mut chan) =>
+                match chan.carrier.recv_choice() {
+                    Ok(true) =>
+                        OffersEach(EachM::Done(Partial::Last(handler(step_chan(chan))))),
+                    Ok(false) =>
+                        OffersEach(EachM::Pending(step_chan(chan))),
+                    Err(e) => {
+                        close_chan(chan);
+                        OffersEach(EachM::Error(e))
+                    },
+                },
+            EachM::Error(err) =>
+                OffersEach(EachM::Error(err)),
+        }
+    }
+}
+
+impl<SR, E, P, A> OffersEach<SR, E, Offer<P, Nil>, A> where SR: Carrier {
+    /// Handle the last offered protocol and finish the offer with the
+    /// flattened outcome of the chosen branch.
+    ///
+    /// A peer selecting a branch past the end of the list fails the offer
+    /// with the `InvalidChoice` error of the carrier, and the channel is
+    /// closed:
+    ///
+    /// ```
+    /// use session_types_ng::*;
+    ///
+    /// // A peer which has been built against a wider protocol
+    /// let (srv, cli) = mpsc::session_channel::<End>();
+    /// let srv: Chan<_, (), Offer<End, Nil>> = Chan::new(srv.shutdown());
+    /// let cli: Chan<_, (), Choose<End, Choose<End, Nil>>> = Chan::new(cli.shutdown());
+    ///
+    /// cli.second().unwrap().close();
+    /// assert_eq!(srv.offer_each().option(Chan::close), Err(mpsc::RecvError::InvalidChoice));
+    /// ```
+    #[must_use = "session channels must reach End and be closed"]
+    pub fn option<F, T>(self, handler: F) -> Result<<Partial<A, T> as Flatten>::Flat, SR::RecvChoiceErr>
+        where F: FnOnce(Chan<SR, E, P>) -> T, Partial<A, T>: Flatten
+    {
+        let outcome = match self.0 {
+            EachM::Done(outcome) =>
+                Partial::Earlier(outcome),
+            EachM::Pending(mut chan) =>
+                match chan.carrier.recv_choice() {
+                    Ok(true) =>
+                        Partial::Last(handler(step_chan(chan))),
+                    Ok(false) => {
+                        close_chan(chan);
+                        return Err(InvalidChoice.into());
+                    },
+                    Err(e) => {
+                        close_chan(chan);
+                        return Err(e);
+                    },
+                },
+            EachM::Error(err) =>
+                return Err(err),
+        };
+        Ok(outcome.flatten())
+    }
+}
+
+/// The left-nested `Partial` accumulated over the outcome types `T...`.
+macro_rules! nested {
+    ($acc:ty;) => { $acc };
+    ($acc:ty; $T:ident $($rest:ident)*) => { nested!(Partial<$acc, $T>; $($rest)*) };
+}
+
+/// A single branch: the outcome is the value itself.
+impl<A> Flatten for nested!(Infallible; A) {
+    type Flat = A;
+
+    fn flatten(self) -> A {
+        match self {
+            Partial::Earlier(never) =>
+                match never {},
+            Partial::Last(a) =>
+                a,
+        }
+    }
+}
+
+/// The outcome of an offer with two branches.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum OneOf2<A, B> {
+    First(A),
+    Second(B),
+}
+
+impl<A, B> Flatten for nested!(Infallible; A B) {
+    type Flat = OneOf2<A, B>;
+
+    fn flatten(self) -> Self::Flat {
+        match self {
+            Partial::Earlier(earlier) =>
+                OneOf2::First(earlier.flatten()),
+            Partial::Last(b) =>
+                OneOf2::Second(b),
+        }
+    }
+}
+
+macro_rules! one_of {
+    ($(#[$attr:meta])* $name:ident from $prev:ident: $($T:ident $variant:ident),+; $Last:ident $last:ident) => {
+        $(#[$attr])*
+        #[derive(Clone, PartialEq, Eq, Debug)]
+        pub enum $name<$($T,)+ $Last> {
+            $($variant($T),)+
+            $last($Last),
+        }
+
+        impl<$($T,)+ $Last> Flatten for nested!(Infallible; $($T)+ $Last) {
+            type Flat = $name<$($T,)+ $Last>;
+
+            fn flatten(self) -> Self::Flat {
+                match self {
+                    Partial::Earlier(earlier) =>
+                        match earlier.flatten() {
+                            $($prev::$variant(value) => $name::$variant(value),)+
+                        },
+                    Partial::Last(value) =>
+                        $name::$last(value),
+                }
+            }
+        }
+    }
+}
+
+one_of!(/// The outcome of an offer with three branches.
+        OneOf3 from OneOf2: A First, B Second; C Third);
+one_of!(/// The outcome of an offer with four branches.
+        OneOf4 from OneOf3: A First, B Second, C Third; D Fourth);
+one_of!(/// The outcome of an offer with five branches.
+        OneOf5 from OneOf4: A First, B Second, C Third, D Fourth; E Fifth);
+one_of!(/// The outcome of an offer with six branches.
+        OneOf6 from OneOf5: A First, B Second, C Third, D Fourth, E Fifth; F Sixth);
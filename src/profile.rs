@@ -0,0 +1,112 @@
+//! Per-step latency breakdown of a session, see `mpsc::Channel`'s
+//! `Chan::measure` for a carrier which records one.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+const BUCKETS: usize = 32;
+
+/// A latency histogram with power of two buckets: bucket `i` counts the
+/// durations from `2^i` up to `2^(i + 1)` microseconds, bucket `0` also
+/// takes everything shorter than a microsecond, and the last bucket
+/// everything longer.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Histogram {
+    buckets: [u64; BUCKETS],
+    count: u64,
+    total: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+impl Default for Histogram {
+    fn default() -> Histogram {
+        Histogram {
+            buckets: [0; BUCKETS],
+            count: 0,
+            total: Duration::from_secs(0),
+            min: Duration::from_secs(0),
+            max: Duration::from_secs(0),
+        }
+    }
+}
+
+impl Histogram {
+    pub fn record(&mut self, latency: Duration) {
+        let micros = latency.as_micros();
+        let bucket = if micros == 0 { 0 } else { 127 - micros.leading_zeros() as usize };
+        self.buckets[bucket.min(BUCKETS - 1)] += 1;
+        self.min = if self.count == 0 { latency } else { self.min.min(latency) };
+        self.max = self.max.max(latency);
+        self.count += 1;
+        self.total += latency;
+    }
+
+    pub fn buckets(&self) -> &[u64] {
+        &self.buckets
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    pub fn min(&self) -> Duration {
+        self.min
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_nanos((self.total.as_nanos() / self.count as u128) as u64)
+        }
+    }
+}
+
+/// Latency histograms keyed by protocol step. A step is labelled with the
+/// name of the protocol type it has been performed on, e.g.
+/// `Recv<Value<u64>, Var<Z>>`.
+#[derive(Clone, Default, Debug)]
+pub struct Profile {
+    steps: BTreeMap<&'static str, Histogram>,
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Profile::default()
+    }
+
+    pub fn record(&mut self, step: &'static str, latency: Duration) {
+        self.steps.entry(step).or_default().record(latency);
+    }
+
+    pub fn step(&self, step: &str) -> Option<&Histogram> {
+        self.steps.get(step)
+    }
+
+    pub fn steps(&self) -> impl Iterator<Item = (&'static str, &Histogram)> {
+        self.steps.iter().map(|(&step, histogram)| (step, histogram))
+    }
+}
+
+/// A report with one line per step, the step dominating the latency first.
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut steps: Vec<_> = self.steps().collect();
+        steps.sort_by_key(|&(_, histogram)| std::cmp::Reverse(histogram.total()));
+        for (step, histogram) in steps {
+            writeln!(f, "{:>8} steps, total {:>12?}, mean {:>12?}, max {:>12?}: {}",
+                     histogram.count(), histogram.total(), histogram.mean(), histogram.max(), step)?;
+        }
+        Ok(())
+    }
+}
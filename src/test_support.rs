@@ -0,0 +1,365 @@
+//! A scripted carrier for testing protocol handlers in isolation.
+//!
+//! A `MockCarrier` plays the peer of the handler under test: it follows a
+//! `Script` of steps, checking every value and choice the handler sends
+//! and answering every receive with a canned one.
+//!
+//! ```
+//! use session_types_ng::*;
+//! use session_types_ng::test_support::{Mock, MockCarrier, Script};
+//!
+//! type Srv = Recv<Mock<u32>, Send<Mock<u32>, End>>;
+//!
+//! fn double(chan: Chan<MockCarrier, (), Srv>) {
+//!     let (chan, Mock(n)) = chan.recv().unwrap();
+//!     chan.send(Mock(n * 2)).unwrap().close();
+//! }
+//!
+//! double(Chan::new(MockCarrier::with_script(Script::new().reply(21u32).expect_send(42u32))));
+//! ```
+//!
+//! A `Plan` goes the other way: it drives a channel through a whole
+//! protocol against a real peer, see `Chan::run_to_end`.
+
+use std::any::{Any, type_name};
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::{fmt, error, thread};
+
+use super::{ChannelSend, ChannelRecv, Carrier, CarrierError, Chan, InvalidChoice, close_chan};
+
+enum Step {
+    Send(&'static str, Box<dyn Fn(&dyn Any) -> bool>),
+    Recv(&'static str, Box<dyn Any>),
+    SendChoice(bool),
+    RecvChoice(bool),
+}
+
+impl Step {
+    fn describe(&self) -> String {
+        match *self {
+            Step::Send(ty, _) =>
+                format!("send of {}", ty),
+            Step::Recv(ty, _) =>
+                format!("recv of {}", ty),
+            Step::SendChoice(choice) =>
+                format!("send of choice {}", choice),
+            Step::RecvChoice(choice) =>
+                format!("recv of choice {}", choice),
+        }
+    }
+}
+
+/// The sequence of steps a `MockCarrier` expects, in order.
+#[derive(Default)]
+pub struct Script {
+    steps: VecDeque<Step>,
+}
+
+impl Script {
+    pub fn new() -> Script {
+        Script::default()
+    }
+
+    /// Expect the handler to send `value`.
+    pub fn expect_send<T>(mut self, value: T) -> Script where T: PartialEq + Debug + 'static {
+        let check = move |sent: &dyn Any| sent.downcast_ref::<T>() == Some(&value);
+        self.steps.push_back(Step::Send(type_name::<T>(), Box::new(check)));
+        self
+    }
+
+    /// Answer the next receive of the handler with `value`.
+    pub fn reply<T>(mut self, value: T) -> Script where T: 'static {
+        self.steps.push_back(Step::Recv(type_name::<T>(), Box::new(value)));
+        self
+    }
+
+    /// Expect the handler to make the `choice` (`true` selects the head of
+    /// a `Choose` list, `false` skips it).
+    pub fn expect_choice(mut self, choice: bool) -> Script {
+        self.steps.push_back(Step::SendChoice(choice));
+        self
+    }
+
+    /// Answer the next offer step of the handler with the `choice`.
+    pub fn choice(mut self, choice: bool) -> Script {
+        self.steps.push_back(Step::RecvChoice(choice));
+        self
+    }
+}
+
+/// A carrier which follows a `Script` instead of talking to a real peer.
+///
+/// Dropping the carrier with steps left in the script panics (unless the
+/// thread is already panicking), so a handler which stops early fails the
+/// test.
+pub struct MockCarrier {
+    script: Script,
+}
+
+impl MockCarrier {
+    pub fn with_script(script: Script) -> MockCarrier {
+        MockCarrier { script }
+    }
+
+    /// Whether every step of the script has been performed.
+    pub fn is_done(&self) -> bool {
+        self.script.steps.is_empty()
+    }
+
+    fn next_step(&mut self, performed: String) -> Result<Step, MockError> {
+        self.script.steps.pop_front().ok_or(MockError::Exhausted { performed })
+    }
+}
+
+impl Drop for MockCarrier {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            if let Some(step) = self.script.steps.front() {
+                panic!("mock carrier dropped with {} pending step(s), next is {}", self.script.steps.len(), step.describe());
+            }
+        }
+    }
+}
+
+/// An error returned by `MockCarrier` when the handler deviates from the
+/// script.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MockError {
+    /// The handler performed a step the script did not expect.
+    Unexpected { expected: String, performed: String },
+    /// The handler sent a value different from the expected one.
+    Mismatch { ty: &'static str },
+    /// The handler performed a step past the end of the script.
+    Exhausted { performed: String },
+    /// The scripted choices selected an offer which does not exist.
+    InvalidChoice,
+}
+
+impl From<InvalidChoice> for MockError {
+    fn from(InvalidChoice: InvalidChoice) -> MockError {
+        MockError::InvalidChoice
+    }
+}
+
+impl fmt::Display for MockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MockError::Unexpected { ref expected, ref performed } =>
+                write!(f, "unexpected {}, the script expects {}", performed, expected),
+            MockError::Mismatch { ty } =>
+                write!(f, "sent value of {} does not match the script", ty),
+            MockError::Exhausted { ref performed } =>
+                write!(f, "unexpected {} past the end of the script", performed),
+            MockError::InvalidChoice =>
+                write!(f, "the script selected an offer which does not exist"),
+        }
+    }
+}
+
+impl error::Error for MockError {}
+
+impl Carrier for MockCarrier {
+    type SendChoiceErr = MockError;
+    fn send_choice(&mut self, choice: bool) -> Result<(), Self::SendChoiceErr> {
+        let performed = Step::SendChoice(choice).describe();
+        match self.next_step(performed.clone())? {
+            Step::SendChoice(expected) if expected == choice =>
+                Ok(()),
+            step =>
+                Err(MockError::Unexpected { expected: step.describe(), performed }),
+        }
+    }
+
+    type RecvChoiceErr = MockError;
+    fn recv_choice(&mut self) -> Result<bool, Self::RecvChoiceErr> {
+        match self.next_step("recv of a choice".to_string())? {
+            Step::RecvChoice(choice) =>
+                Ok(choice),
+            step =>
+                Err(MockError::Unexpected { expected: step.describe(), performed: "recv of a choice".to_string() }),
+        }
+    }
+}
+
+impl CarrierError for MockCarrier {
+    type Error = MockError;
+}
+
+/// A value transferred over `MockCarrier`, see `mpsc::Value` for the
+/// reason of the wrapper.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Mock<T>(pub T);
+
+impl<T> ChannelSend for Mock<T> where T: 'static {
+    type Crr = MockCarrier;
+    type Err = MockError;
+
+    fn send(self, carrier: &mut Self::Crr) -> Result<(), Self::Err> {
+        let ty = type_name::<T>();
+        let performed = format!("send of {}", ty);
+        match carrier.next_step(performed.clone())? {
+            Step::Send(_, ref check) if check(&self.0) =>
+                Ok(()),
+            Step::Send(expected_ty, _) if expected_ty == ty =>
+                Err(MockError::Mismatch { ty }),
+            step =>
+                Err(MockError::Unexpected { expected: step.describe(), performed }),
+        }
+    }
+}
+
+impl<T> ChannelRecv for Mock<T> where T: 'static {
+    type Crr = MockCarrier;
+    type Err = MockError;
+
+    fn recv(carrier: &mut Self::Crr) -> Result<Self, Self::Err> {
+        let performed = format!("recv of {}", type_name::<T>());
+        match carrier.next_step(performed.clone())? {
+            Step::Recv(expected_ty, value) =>
+                value.downcast::<T>()
+                    .map(|value| Mock(*value))
+                    .map_err(|_| MockError::Unexpected { expected: format!("recv of {}", expected_ty), performed }),
+            step =>
+                Err(MockError::Unexpected { expected: step.describe(), performed }),
+        }
+    }
+}
+
+type PlanStep<SR> = Box<dyn FnOnce(&mut SR) -> Result<(), String>>;
+
+/// A protocol run described as data: the steps `Chan::run_to_end` performs
+/// over a channel, in order, checking what the peer sends on the way.
+pub struct Plan<SR> {
+    steps: Vec<PlanStep<SR>>,
+}
+
+impl<SR> Default for Plan<SR> {
+    fn default() -> Plan<SR> {
+        Plan { steps: Vec::new() }
+    }
+}
+
+impl<SR> Plan<SR> where SR: Carrier + 'static {
+    pub fn new() -> Plan<SR> {
+        Plan::default()
+    }
+
+    /// Send `value` to the peer.
+    pub fn send<T>(mut self, value: T) -> Plan<SR> where T: ChannelSend<Crr = SR> + 'static, T::Err: Debug {
+        self.steps.push(Box::new(move |carrier: &mut SR| {
+            value.send(carrier).map_err(|e| format!("send of {} failed: {:?}", type_name::<T>(), e))
+        }));
+        self
+    }
+
+    /// Receive a value from the peer and check that it equals `expected`.
+    pub fn expect_recv<T>(mut self, expected: T) -> Plan<SR>
+        where T: ChannelRecv<Crr = SR> + PartialEq + Debug + 'static, T::Err: Debug
+    {
+        self.steps.push(Box::new(move |carrier: &mut SR| {
+            match T::recv(carrier) {
+                Ok(ref value) if *value == expected =>
+                    Ok(()),
+                Ok(value) =>
+                    Err(format!("received {:?}, the plan expects {:?}", value, expected)),
+                Err(e) =>
+                    Err(format!("recv of {} failed: {:?}", type_name::<T>(), e)),
+            }
+        }));
+        self
+    }
+
+    /// Select the branch at `index` of a `Choose` list.
+    pub fn choose(mut self, index: usize) -> Plan<SR> where SR::SendChoiceErr: Debug {
+        self.steps.push(Box::new(move |carrier: &mut SR| {
+            let mut choices = vec![false; index];
+            choices.push(true);
+            carrier.send_choice_n(&choices).map_err(|e| format!("choice of branch {} failed: {:?}", index, e))
+        }));
+        self
+    }
+
+    /// Expect the peer to select the branch at `index` of an `Offer` list.
+    pub fn expect_offer(mut self, index: usize) -> Plan<SR> where SR::RecvChoiceErr: Debug {
+        self.steps.push(Box::new(move |carrier: &mut SR| {
+            let mut chosen = 0;
+            loop {
+                match carrier.recv_choice() {
+                    Ok(true) if chosen == index =>
+                        return Ok(()),
+                    Ok(true) =>
+                        return Err(format!("the peer selected branch {}, the plan expects {}", chosen, index)),
+                    Ok(false) =>
+                        chosen += 1,
+                    Err(e) =>
+                        return Err(format!("offer failed: {:?}", e)),
+                }
+            }
+        }));
+        self
+    }
+}
+
+/// A step of a `Plan` which has failed, the session is over.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PlanError {
+    /// The position of the step in the plan.
+    pub step: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "step {} of the plan: {}", self.step, self.reason)
+    }
+}
+
+impl error::Error for PlanError {}
+
+impl<SR, E, P> Chan<SR, E, P> where SR: Carrier {
+    /// Drive the session through every step of the `plan` and close it,
+    /// e.g. to test a peer implementation against a scripted protocol run.
+    /// Stops at the first failing step.
+    ///
+    /// The plan is type erased, so nothing relates its steps to the
+    /// protocol `P`: each step works at the level of the carrier, and the
+    /// protocol state of the channel is not followed (the carrier is not
+    /// notified of the steps either). `Rec` and `Var` take no step of their
+    /// own, and a run of choices made with `cddr` and the like is expected
+    /// one branch at a time by `expect_offer` all the same.
+    ///
+    /// ```
+    /// use session_types_ng::*;
+    /// use session_types_ng::mpsc::Value;
+    /// use session_types_ng::test_support::Plan;
+    ///
+    /// type Srv = Recv<Value<u32>, Choose<End, Choose<Send<Value<u32>, End>, Nil>>>;
+    ///
+    /// let (srv, cli) = mpsc::session_channel::<Srv>();
+    /// std::thread::spawn(move || {
+    ///     let (chan, n) = srv.recv_value().unwrap();
+    ///     chan.second().unwrap().send_value(n * 2).unwrap().close();
+    /// });
+    /// let plan = Plan::new().send(Value(21u32)).expect_offer(1).expect_recv(Value(42u32));
+    /// unsafe { cli.run_to_end(plan) }.unwrap();
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// Carriers are allowed to rely on the protocol for the types of the
+    /// values they transfer, e.g. `mpsc::Channel` reinterprets what it
+    /// receives as the type the protocol names. The plan must follow `P`
+    /// step by step, with the very value types it names, all the way to
+    /// `End`.
+    pub unsafe fn run_to_end(mut self, plan: Plan<SR>) -> Result<(), PlanError> {
+        for (step, perform) in plan.steps.into_iter().enumerate() {
+            if let Err(reason) = perform(&mut self.carrier) {
+                close_chan(self);
+                return Err(PlanError { step, reason });
+            }
+        }
+        close_chan(self);
+        Ok(())
+    }
+}